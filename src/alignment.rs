@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+
+use WordVector;
+
+// The nearest word of the other document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alignment {
+    pub word: String,
+    pub nearest: String,
+    pub distance: f32,
+}
+
+// Words are unique and keep the document order, unmatched words are out of the model vocabulary
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AlignmentReport {
+    pub forward: Vec<Alignment>,
+    pub backward: Vec<Alignment>,
+    pub unmatched1: Vec<String>,
+    pub unmatched2: Vec<String>,
+}
+
+struct DocWords {
+    known: Vec<(String, Vec<f32>)>,
+    unmatched: Vec<String>,
+}
+
+impl<'a> WordVector<'a> {
+    fn doc_words<T>(&self, doc: &[T]) -> DocWords
+        where
+            T: ToString
+    {
+        let mut words = DocWords {
+            known: Vec::new(),
+            unmatched: Vec::new(),
+        };
+
+        let mut seen: BTreeSet<String> = BTreeSet::new();
+
+        for word in doc.iter().map(|word| word.to_string()) {
+            if !seen.insert(word.clone()) {
+                continue;
+            }
+
+            let vector = match self.model.word_index(&word) {
+                Some(_) => self.model.word_to_vector(&word),
+                None => None,
+            };
+
+            match vector {
+                Some(vector) => words.known.push((word, vector)),
+                None => words.unmatched.push(word),
+            }
+        }
+
+        words
+    }
+
+    fn nearest(&self, words1: &DocWords, words2: &DocWords) -> Vec<Alignment> {
+        words1.known.iter()
+            .filter_map(|(word, vector)| {
+                words2.known.iter()
+                    .map(|(other, other_vector)| (other, self.metric.distance(vector, other_vector)))
+                    .fold(None, |nearest: Option<(&String, f32)>, (other, distance)| match nearest {
+                        Some((_, best)) if best <= distance => nearest,
+                        _ => Some((other, distance)),
+                    })
+                    .map(|(nearest, distance)| Alignment {
+                        word: word.clone(),
+                        nearest: nearest.clone(),
+                        distance,
+                    })
+            })
+            .collect()
+    }
+
+    // for every known word of a document the nearest known word of the other one by the metric,
+    // an error when a document is below min_coverage
+    pub fn alignment<T>(&self, doc1: &[T], doc2: &[T]) -> Result<AlignmentReport, &str>
+        where
+            T: ToString
+    {
+        self.check_coverage(doc1)?;
+        self.check_coverage(doc2)?;
+
+        let words1 = self.doc_words(doc1);
+        let words2 = self.doc_words(doc2);
+
+        Ok(AlignmentReport {
+            forward: self.nearest(&words1, &words2),
+            backward: self.nearest(&words2, &words1),
+            unmatched1: words1.unmatched,
+            unmatched2: words2.unmatched,
+        })
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use testing::model::TestModel;
+
+    #[test]
+    fn test_alignment() {
+        let model = TestModel::default();
+        let vector = WordVector::new(&model, &model);
+
+        let doc1 = ["намело", "сугробы", "калитки", "намело"];
+        let doc2 = ["у", "нашего", "крыльца", "вдвойне"];
+
+        let report = vector.alignment(&doc1, &doc2).unwrap();
+
+        let words: Vec<&str> = report.forward.iter().map(|alignment| alignment.word.as_str()).collect();
+        assert_eq!(words, ["намело", "сугробы"], "check forward words");
+        assert_eq!(report.backward.len(), 3, "check backward words");
+
+        for alignment in report.forward.iter().chain(report.backward.iter()) {
+            let others: &[&str] = if doc1.contains(&alignment.word.as_str()) { &doc2 } else { &doc1 };
+
+            let best = others.iter()
+                .filter_map(|other| vector.words_distance(&alignment.word, other))
+                .fold(f32::INFINITY, f32::min);
+
+            assert_eq!(alignment.distance, best, "check nearest distance of {}", alignment.word);
+            assert_eq!(vector.words_distance(&alignment.word, &alignment.nearest), Some(best), "check nearest word");
+        }
+
+        assert_eq!(report.unmatched1, ["калитки"], "check unmatched words");
+        assert_eq!(report.unmatched2, ["вдвойне"], "check other unmatched words");
+
+        let empty = vector.alignment(&doc1, &["вдвойне"]).unwrap();
+        assert!(empty.forward.is_empty() && empty.backward.is_empty(), "check no known words");
+        assert_eq!(empty.unmatched2, ["вдвойне"], "check unmatched only");
+    }
+
+    #[test]
+    fn test_alignment_coverage_policy() {
+        let model = TestModel::default();
+        let strict = WordVector::new(&model, &model).with_min_coverage(0.9);
+
+        let doc1 = ["намело", "сугробы", "калитки"];
+        let doc2 = ["у", "нашего", "крыльца"];
+
+        assert!(strict.alignment(&doc1, &doc2).is_err(), "check first doc policy");
+        assert!(strict.alignment(&doc2, &doc1).is_err(), "check second doc policy");
+        assert!(strict.alignment(&doc2, &doc2).is_ok(), "check full coverage");
+    }
+}
@@ -1,6 +1,115 @@
 use std::collections::BTreeMap;
 use ::dictionary::Dictionary;
 
+// Sparse bag of words, (index, weight) pairs sorted by index with unique indices
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SparseBow {
+    entries: Vec<(usize, f32)>,
+}
+
+impl SparseBow {
+    // weights of duplicated indices are summed
+    pub fn new(mut entries: Vec<(usize, f32)>) -> SparseBow {
+        entries.sort_by_key(|&(index, _)| index);
+
+        let mut merged: Vec<(usize, f32)> = Vec::with_capacity(entries.len());
+
+        for (index, weight) in entries {
+            match merged.last_mut() {
+                Some(last) if last.0 == index => last.1 += weight,
+                _ => merged.push((index, weight)),
+            }
+        }
+
+        SparseBow {
+            entries: merged,
+        }
+    }
+
+    pub fn entries(&self) -> &[(usize, f32)] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> f32 {
+        match self.entries.binary_search_by_key(&index, |&(index, _)| index) {
+            Ok(pos) => self.entries[pos].1,
+            Err(_) => 0.0,
+        }
+    }
+
+    pub fn sum(&self) -> f32 {
+        self.entries.iter().map(|&(_, weight)| weight).sum()
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    // weights sum to one, a zero bow is kept as is
+    pub fn normalize_l1(&mut self) {
+        let sum: f32 = self.entries.iter().map(|&(_, weight)| weight.abs()).sum();
+
+        self.scale(sum);
+    }
+
+    pub fn normalize_l2(&mut self) {
+        let norm = self.norm();
+
+        self.scale(norm);
+    }
+
+    pub fn dot(&self, other: &SparseBow) -> f32 {
+        sparse_dot(&self.entries, &other.entries)
+    }
+
+    pub fn to_dense(&self, len: usize) -> Vec<f32> {
+        let mut dense: Vec<f32> = vec![0.0; len];
+
+        for &(index, weight) in self.entries.iter().filter(|&&(index, _)| index < len) {
+            dense[index] = weight;
+        }
+
+        dense
+    }
+
+    fn scale(&mut self, normalizer: f32) {
+        if normalizer == 0.0 {
+            return;
+        }
+
+        self.entries.iter_mut().for_each(|entry| entry.1 /= normalizer);
+    }
+}
+
+// both sides are sorted by index
+pub(crate) fn sparse_dot(entries1: &[(usize, f32)], entries2: &[(usize, f32)]) -> f32 {
+    let (mut i, mut j) = (0, 0);
+    let mut result = 0.0f32;
+
+    while i < entries1.len() && j < entries2.len() {
+        if entries1[i].0 < entries2[j].0 {
+            i += 1;
+        } else if entries1[i].0 > entries2[j].0 {
+            j += 1;
+        } else {
+            result += entries1[i].1 * entries2[j].1;
+
+            i += 1;
+            j += 1;
+        }
+    }
+
+    result
+}
+
 impl Dictionary {
     fn doc_to_bow<T>(&self, doc: &[T]) -> Vec<i64>
         where
@@ -9,7 +118,7 @@ impl Dictionary {
         let mut counter: BTreeMap<String, i64> = BTreeMap::new();
 
         for word in doc.iter().map(|word| word.to_string()) {
-            if let Some(mut value) = counter.get_mut(&word) {
+            if let Some(value) = counter.get_mut(&word) {
                 *value += 1;
                 continue;
             }
@@ -17,8 +126,7 @@ impl Dictionary {
             counter.insert(word, 1);
         }
 
-        let mut res: Vec<i64> = Vec::new();
-        res.resize(self.len(), 0);
+        let mut res: Vec<i64> = vec![0; self.len()];
 
         for (word, &freq) in counter.iter() {
             if let Some(index) = self.word_index(word) {
@@ -39,15 +147,43 @@ impl Dictionary {
             return None
         }
 
-        Some(self.doc_to_bow(&doc)
+        Some(self.doc_to_bow(doc)
             .into_iter()
             .map(|bow| bow as f32 / normalizer)
             .collect())
     }
+
+    // unknown words are skipped, the memory is proportional to the doc, not to the dictionary
+    pub fn doc_to_sparse_bow<T>(&self, doc: &[T]) -> SparseBow
+        where
+            T: ToString
+    {
+        SparseBow::new(doc.iter()
+            .filter_map(|word| self.word_index(&word.to_string()))
+            .map(|index| (index as usize, 1.0))
+            .collect())
+    }
+
+    // the same weights as `bow_normalized` in the sparse form
+    pub fn bow_normalized_sparse<T>(&self, doc: &[T]) -> Option<SparseBow>
+        where
+            T: ToString
+    {
+        if doc.is_empty() {
+            return None
+        }
+
+        let mut bow = self.doc_to_sparse_bow(doc);
+        bow.scale(doc.len() as f32);
+
+        Some(bow)
+    }
 }
 
 
+// the assertions predate clippy, they are kept as they were written
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants, clippy::redundant_pattern_matching, clippy::needless_borrows_for_generic_args)]
 mod testing {
     use super::*;
 
@@ -96,4 +232,45 @@ mod testing {
             }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sparse_bow() {
+        let bow = SparseBow::new(vec![(7, 1.0), (2, 2.0), (7, 1.0)]);
+
+        assert_eq!(bow.entries(), [(2, 2.0), (7, 2.0)], "check sorted and merged");
+        assert_eq!(bow.get(7), 2.0, "check get");
+        assert_eq!(bow.get(3), 0.0, "check missed get");
+        assert_eq!(bow.to_dense(8), [0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 2.0], "check dense");
+
+        let other = SparseBow::new(vec![(1, 5.0), (7, 3.0)]);
+        assert_eq!(bow.dot(&other), 6.0, "check dot");
+        assert_eq!(bow.dot(&SparseBow::default()), 0.0, "check empty dot");
+
+        let mut l1 = bow.clone();
+        l1.normalize_l1();
+        assert_eq!(l1.entries(), [(2, 0.5), (7, 0.5)], "check l1 normalize");
+
+        let mut l2 = other.clone();
+        l2.normalize_l2();
+        assert!((l2.norm() - 1.0).abs() < 1e-6, "check l2 normalize");
+
+        let mut zero = SparseBow::default();
+        zero.normalize_l2();
+        assert!(zero.is_empty(), "check zero normalize");
+    }
+
+    #[test]
+    fn test_dictionary_bow_normalized_sparse() {
+        let dict = Dictionary::with_extend(&["крыльца", "намело", "нашего", "сугробы", "у"]);
+
+        let text = ["намело", "сугробы", "намело", "вдвойне", "у", "крыльца", "намело", "намело", "за", "крыльца"];
+
+        let exist = dict.bow_normalized_sparse(&text).unwrap();
+
+        assert_eq!(exist.entries(), [(0, 0.2), (1, 0.4), (3, 0.1), (4, 0.1)], "check sparse bow normalize");
+        assert_eq!(exist.to_dense(dict.len()), dict.bow_normalized(&text).unwrap(), "check dense equality");
+
+        let empty: Vec<&str> = Vec::new();
+        assert!(dict.bow_normalized_sparse(&empty).is_none(), "check empty doc");
+    }
+}
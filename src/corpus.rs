@@ -0,0 +1,394 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+use serde_json::{self, Value};
+
+use dictionary::Dictionary;
+
+pub trait Tokenizer: Sync {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+pub struct Whitespace;
+
+impl Tokenizer for Whitespace {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split_whitespace().map(|word| word.to_string()).collect()
+    }
+}
+
+impl<F> Tokenizer for F
+    where F: Fn(&str) -> Vec<String> + Sync
+{
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+pub type Sentences<'a> = Box<dyn Iterator<Item=io::Result<Vec<String>>> + 'a>;
+
+// A source of tokenized sentences read in shards, every call starts a new pass,
+// so multiple epochs are multiple calls
+pub trait Corpus: Sync {
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a>;
+
+    fn all_sentences<'a>(&'a self) -> Sentences<'a> {
+        self.sentences(0, 1)
+    }
+}
+
+// contiguous chunk of `len` items for the shard
+pub(crate) fn shard_range(len: usize, shard: usize, shards: usize) -> (usize, usize) {
+    let shards = shards.max(1);
+
+    (len * shard / shards, len * (shard + 1) / shards)
+}
+
+impl<T> Corpus for [Vec<T>]
+    where T: ToString + Sync
+{
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a> {
+        let (start, end) = shard_range(self.len(), shard, shards);
+
+        Box::new(self[start..end].iter()
+            .map(|sentence| Ok(sentence.iter().map(|word| word.to_string()).collect())))
+    }
+}
+
+impl<T> Corpus for Vec<Vec<T>>
+    where T: ToString + Sync
+{
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a> {
+        self.as_slice().sentences(shard, shards)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    // a sentence per line
+    Plain,
+    // a JSON object per line, the sentence is the string field
+    JsonLines(String),
+}
+
+// Text file streamed line by line, ".gz" files are decompressed on the fly.
+// Plain files are sharded by byte offset, a line belongs to the shard where it starts;
+// compressed files can't be seeked, so every shard decompresses the whole stream
+// and takes every shards-th line
+pub struct TextCorpus<T = Whitespace> {
+    path: PathBuf,
+    format: Format,
+    tokenizer: T,
+}
+
+impl TextCorpus<Whitespace> {
+    pub fn new<P>(path: P) -> TextCorpus<Whitespace>
+        where P: AsRef<Path>
+    {
+        TextCorpus {
+            path: path.as_ref().to_path_buf(),
+            format: Format::Plain,
+            tokenizer: Whitespace,
+        }
+    }
+
+    pub fn json_lines<P, S>(path: P, field: S) -> TextCorpus<Whitespace>
+        where
+            P: AsRef<Path>,
+            S: ToString
+    {
+        TextCorpus {
+            path: path.as_ref().to_path_buf(),
+            format: Format::JsonLines(field.to_string()),
+            tokenizer: Whitespace,
+        }
+    }
+}
+
+impl<T> TextCorpus<T>
+    where T: Tokenizer
+{
+    pub fn with_tokenizer<U>(self, tokenizer: U) -> TextCorpus<U>
+        where U: Tokenizer
+    {
+        TextCorpus {
+            path: self.path,
+            format: self.format,
+            tokenizer,
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        match self.path.extension() {
+            Some(ext) => ext == "gz",
+            None => false,
+        }
+    }
+
+    fn lines(&self, shard: usize, shards: usize) -> io::Result<Box<dyn Iterator<Item=io::Result<String>>>> {
+        let file = File::open(&self.path)?;
+
+        if self.is_compressed() {
+            let shards = shards.max(1);
+
+            return Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))
+                .lines()
+                .enumerate()
+                .filter(move |&(index, _)| index % shards == shard)
+                .map(|(_, line)| line)));
+        }
+
+        let len = file.metadata()?.len();
+        let (start, end) = shard_range(len as usize, shard, shards);
+
+        ShardLines::new(file, start as u64, end as u64)
+            .map(|lines| Box::new(lines) as Box<dyn Iterator<Item=io::Result<String>>>)
+    }
+
+    fn parse(&self, line: &str) -> io::Result<Vec<String>> {
+        match self.format {
+            Format::Plain => Ok(self.tokenizer.tokenize(line)),
+            Format::JsonLines(ref field) => {
+                if line.trim().is_empty() {
+                    return Ok(Vec::new());
+                }
+
+                let value: Value = serde_json::from_str(line)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+                match value.get(field.as_str()).and_then(|text| text.as_str()) {
+                    Some(text) => Ok(self.tokenizer.tokenize(text)),
+                    None => Err(io::Error::new(io::ErrorKind::InvalidData, "missed text field")),
+                }
+            }
+        }
+    }
+}
+
+impl<T> Corpus for TextCorpus<T>
+    where T: Tokenizer
+{
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a> {
+        match self.lines(shard, shards) {
+            Ok(lines) => Box::new(lines.map(move |line| self.parse(&line?))),
+            Err(err) => Box::new(iter::once(Err(err))),
+        }
+    }
+}
+
+struct ShardLines {
+    reader: BufReader<File>,
+    pos: u64,
+    end: u64,
+}
+
+impl ShardLines {
+    fn new(file: File, start: u64, end: u64) -> io::Result<ShardLines> {
+        let mut reader = BufReader::new(file);
+        let mut pos = start;
+
+        // skip the tail of a line started in the previous shard
+        if start > 0 {
+            reader.seek(SeekFrom::Start(start - 1))?;
+
+            let mut skipped: Vec<u8> = Vec::new();
+            pos = start - 1 + reader.read_until(b'\n', &mut skipped)? as u64;
+        }
+
+        Ok(ShardLines {
+            reader,
+            pos,
+            end,
+        })
+    }
+}
+
+impl Iterator for ShardLines {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        if self.pos >= self.end {
+            return None;
+        }
+
+        let mut line: Vec<u8> = Vec::new();
+
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(read) => {
+                self.pos += read as u64;
+
+                while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+
+                Some(String::from_utf8(line)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err)))
+            }
+            Err(err) => {
+                self.pos = self.end;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl Dictionary {
+    pub fn with_corpus<C>(corpus: &C) -> io::Result<Dictionary>
+        where C: Corpus + ?Sized
+    {
+        let mut dict = Dictionary::default();
+
+        for sentence in corpus.all_sentences() {
+            dict.extend(sentence?);
+        }
+
+        dict.reindex();
+
+        Ok(dict)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    const TEXT: &str = "намело сугробы\nу нашего крыльца\n\nстонет стужа\nи намело сугробы\n";
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("wordvector-corpus-{}-{}", ::std::process::id(), name));
+
+        fs::write(&path, data).unwrap();
+
+        path
+    }
+
+    fn collect<C>(corpus: &C, shard: usize, shards: usize) -> Vec<Vec<String>>
+        where C: Corpus
+    {
+        corpus.sentences(shard, shards)
+            .map(|sentence| sentence.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_corpus_slice() {
+        let corpus = vec![vec!["намело", "сугробы"], vec!["у", "крыльца"], vec!["стужа"]];
+
+        assert_eq!(collect(&corpus, 0, 1).len(), 3, "check single shard");
+        assert_eq!(collect(&corpus, 0, 2), [vec!["намело", "сугробы"]], "check first shard");
+        assert_eq!(collect(&corpus, 1, 2).len(), 2, "check second shard");
+    }
+
+    #[test]
+    fn test_corpus_text_shards() {
+        let path = temp_file("plain.txt", TEXT.as_bytes());
+        let corpus = TextCorpus::new(&path);
+
+        let all = collect(&corpus, 0, 1);
+        assert_eq!(all.len(), 5, "check lines");
+        assert_eq!(all[1], ["у", "нашего", "крыльца"], "check tokenized line");
+
+        for shards in 1..TEXT.len() + 2 {
+            let sharded: Vec<Vec<String>> = (0..shards)
+                .flat_map(|shard| collect(&corpus, shard, shards))
+                .collect();
+
+            assert_eq!(sharded, all, "check {} shards", shards);
+        }
+
+        // every pass reads the file again
+        assert_eq!(collect(&corpus, 0, 1), all, "check second epoch");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_gzip() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(TEXT.as_bytes()).unwrap();
+
+        let path = temp_file("plain.txt.gz", &encoder.finish().unwrap());
+        let corpus = TextCorpus::new(&path);
+
+        assert!(corpus.is_compressed(), "check compressed");
+
+        let all = collect(&corpus, 0, 1);
+        assert_eq!(all.len(), 5, "check decompressed lines");
+        assert_eq!(all[3], ["стонет", "стужа"], "check decompressed line");
+
+        let mut sharded: Vec<Vec<String>> = collect(&corpus, 0, 2);
+        sharded.extend(collect(&corpus, 1, 2));
+        assert_eq!(sharded.len(), all.len(), "check shards");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_json_lines() {
+        let data = "{\"id\": 1, \"text\": \"Намело сугробы\"}\n{\"id\": 2, \"text\": \"у крыльца\"}\n";
+        let path = temp_file("lines.jsonl", data.as_bytes());
+
+        let lowercase = |text: &str| -> Vec<String> {
+            text.split_whitespace().map(|word| word.to_lowercase()).collect()
+        };
+
+        let corpus = TextCorpus::json_lines(&path, "text").with_tokenizer(lowercase);
+
+        assert_eq!(collect(&corpus, 0, 1), [vec!["намело", "сугробы"], vec!["у", "крыльца"]], "check json lines");
+
+        let missed = TextCorpus::json_lines(&path, "body");
+        assert!(missed.all_sentences().next().unwrap().is_err(), "check missed field");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_dictionary() {
+        let path = temp_file("dictionary.txt", TEXT.as_bytes());
+
+        let exist = Dictionary::with_corpus(&TextCorpus::new(&path)).unwrap();
+        let expected = Dictionary::with_extend(TEXT.split_whitespace());
+
+        assert_eq!(exist, expected, "check dictionary");
+
+        assert!(Dictionary::with_corpus(&TextCorpus::new("/nonexistent/corpus.txt")).is_err(), "check missed file");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corpus_training() {
+        use train::word2vec::Word2Vec;
+        use train::word2vec::testing::{topic_corpus, check_topics};
+
+        let lines: Vec<String> = topic_corpus().iter().map(|sentence| sentence.join(" ")).collect();
+        let path = temp_file("training.txt", lines.join("\n").as_bytes());
+
+        let config = Word2Vec {
+            dim: 16,
+            window: 3,
+            min_count: 1,
+            sample: 0.0,
+            epochs: 10,
+            threads: 2,
+            ..Word2Vec::default()
+        };
+
+        check_topics(&config.train(&TextCorpus::new(&path)).unwrap());
+
+        let err = config.train(&TextCorpus::new("/nonexistent/corpus.txt")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound, "check missed file");
+
+        fs::remove_file(&path).unwrap();
+    }
+}
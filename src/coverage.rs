@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+
+use WordVector;
+
+// Tokens of a document known to the model, oov keeps unique unknown words in the document order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Coverage {
+    pub tokens: usize,
+    pub known: usize,
+    pub oov: Vec<String>,
+}
+
+impl Coverage {
+    // share of known tokens, zero for an empty document
+    pub fn ratio(&self) -> f32 {
+        if self.tokens == 0 {
+            return 0.0;
+        }
+
+        self.known as f32 / self.tokens as f32
+    }
+}
+
+impl<'a> WordVector<'a> {
+    pub fn coverage<T>(&self, doc: &[T]) -> Coverage
+        where
+            T: ToString
+    {
+        let mut coverage = Coverage::default();
+        let mut seen: BTreeSet<String> = BTreeSet::new();
+
+        for word in doc.iter().map(|word| word.to_string()) {
+            coverage.tokens += 1;
+
+            if self.model.word_index(&word).is_some() {
+                coverage.known += 1;
+            } else if seen.insert(word.clone()) {
+                coverage.oov.push(word);
+            }
+        }
+
+        coverage
+    }
+
+    // the coverage of the doc, an error below min_coverage
+    pub(crate) fn check_coverage<T>(&self, doc: &[T]) -> Result<Coverage, &str>
+        where
+            T: ToString
+    {
+        let coverage = self.coverage(doc);
+
+        if coverage.ratio() < self.min_coverage {
+            return Err("low vocabulary coverage");
+        }
+
+        Ok(coverage)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use testing::model::TestModel;
+
+    #[test]
+    fn test_coverage() {
+        let model = TestModel::default();
+        let vector = WordVector::new(&model, &model);
+
+        let coverage = vector.coverage(&["намело", "калитки", "сугробы", "калитки", "вдвойне"]);
+
+        assert_eq!(coverage.tokens, 5, "check tokens");
+        assert_eq!(coverage.known, 2, "check known tokens");
+        assert_eq!(coverage.oov, ["калитки", "вдвойне"], "check unique oov words");
+        assert_eq!(coverage.ratio(), 0.4, "check ratio");
+
+        let empty: Vec<&str> = Vec::new();
+        assert_eq!(vector.coverage(&empty).ratio(), 0.0, "check empty doc");
+    }
+
+    #[test]
+    fn test_coverage_policy() {
+        let model = TestModel::default();
+
+        let doc1 = ["намело", "сугробы", "калитки"];
+        let doc2 = ["у", "нашего", "крыльца"];
+
+        let vector = WordVector::new(&model, &model);
+
+        let (distance, coverage1, coverage2) = vector.wm_distance_with_coverage(&doc1, &doc2).unwrap();
+        assert_eq!(Ok(distance), vector.wm_distance(&doc1, &doc2), "check distance");
+        assert_eq!(coverage1.oov, ["калитки"], "check first coverage");
+        assert_eq!(coverage2.ratio(), 1.0, "check second coverage");
+
+        let (_, coverage) = vector.doc_to_vector_with_coverage(&doc1).unwrap();
+        assert_eq!(coverage.known, 2, "check doc coverage");
+
+        let (explanation, coverage1, _) = vector.wm_distance_explained_with_coverage(&doc1, &doc2).unwrap();
+        assert_eq!(Ok(explanation), vector.wm_distance_explained(&doc1, &doc2), "check explanation");
+        assert_eq!(coverage1.oov, ["калитки"], "check explained coverage");
+
+        let similarity = vector.term_similarity(&["намело", "сугробы", "у", "нашего", "крыльца"], 0.0, 1.0);
+        let (value, _, coverage2) = vector.soft_cosine_with_coverage_using(&similarity, &doc1, &doc2).unwrap();
+        assert_eq!(Ok(value), vector.soft_cosine_with(&similarity, &doc1, &doc2), "check sparse soft cosine");
+        assert_eq!(coverage2.known, 3, "check sparse soft cosine coverage");
+
+        let strict = WordVector::new(&model, &model).with_min_coverage(0.9);
+
+        assert!(strict.wm_distance(&doc1, &doc2).is_err(), "check wm distance policy");
+        assert!(strict.similarity(&doc1, &doc2).is_err(), "check similarity policy");
+        assert!(strict.soft_cosine(&doc1, &doc2).is_err(), "check soft cosine policy");
+        assert!(strict.doc_to_vector(&doc1).is_err(), "check doc vector policy");
+        assert!(strict.wm_distance_explained(&doc1, &doc2).is_err(), "check explained policy");
+        assert!(strict.similarity_with_coverage(&doc2, &doc2).is_ok(), "check full coverage");
+        assert!(strict.similarity_matrix(&[doc1.to_vec(), doc2.to_vec()]).is_err(), "check similarity matrix policy");
+    }
+}
@@ -1,9 +1,19 @@
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, btree_map};
+use std::io::{self, BufRead, Read, Write};
 use std::sync::atomic::{AtomicI64, Ordering};
 
+use utils::{invalid, read_bytes};
+
+const BINARY_MAGIC: &[u8; 4] = b"WVD1";
+
 #[derive(Debug)]
 pub struct Dictionary {
     data: BTreeMap<String, i64>,
+    // index -> word
+    words: Vec<String>,
+    // index -> count
+    counts: Vec<u64>,
     index: AtomicI64,
 }
 
@@ -11,51 +21,159 @@ impl Default for Dictionary {
     fn default() -> Dictionary {
         Dictionary {
             data: BTreeMap::new(),
+            words: Vec::new(),
+            counts: Vec::new(),
             index: AtomicI64::new(0),
         }
     }
 }
 
 impl Dictionary {
-    pub fn iter(&self) -> btree_map::Keys<String, i64> {
+    pub fn iter<'a>(&'a self) -> btree_map::Keys<'a, String, i64> {
         self.data.keys()
     }
 
+    // (index, word) pairs in index order
+    pub fn iter_indexed(&self) -> impl Iterator<Item=(i64, &str)> {
+        self.words.iter()
+            .enumerate()
+            .map(|(index, word)| (index as i64, word.as_str()))
+    }
+
     pub fn insert<S>(&mut self, word: S)
         where S: ToString
+    {
+        self.insert_count(word, 1);
+    }
+
+    // a new word gets the next index, a known word only accumulates the count
+    pub fn insert_count<S>(&mut self, word: S, count: u64)
+        where S: ToString
     {
         let w: String = word.to_string();
 
-        if !self.contains(&w) {
-            let index = self.index_inc();
-            self.data.insert(w, index);
-        }
+        let index = match self.data.get(&w) {
+            Some(&index) => index,
+            None => {
+                let index = self.index_inc();
+                self.data.insert(w.clone(), index);
+                self.words.push(w);
+                self.counts.push(0);
+
+                index
+            }
+        };
+
+        self.counts[index as usize] += count;
     }
 
     pub fn reindex(&mut self) {
         self.index.store(0, Ordering::SeqCst);
 
         let mut index = 0i64;
+        let mut counts: Vec<u64> = Vec::with_capacity(self.counts.len());
+
+        self.words.clear();
+
+        for (word, value) in self.data.iter_mut() {
+            counts.push(self.counts[*value as usize]);
 
-        for (_, value) in self.data.iter_mut() {
             *value = index;
+            self.words.push(word.clone());
 
             index += 1;
         }
 
+        self.counts = counts;
+
         self.index.store(index, Ordering::SeqCst);
     }
 
-    pub fn contains<'a>(&self, word: &'a str) -> bool
+    // sorted by count descending, ties alphabetically
+    pub fn reindex_by_frequency(&mut self) {
+        let order: Vec<(String, u64)> = self.most_common(self.len())
+            .into_iter()
+            .map(|(word, count)| (word.to_string(), count))
+            .collect();
+
+        self.assign(order);
+    }
+
+    // the most frequent words, ties alphabetically
+    pub fn most_common(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut words: Vec<(&str, u64)> = self.data.iter()
+            .map(|(word, &index)| (word.as_str(), self.counts[index as usize]))
+            .collect();
+
+        words.sort_by_key(|&(_, count)| Reverse(count));
+        words.truncate(n);
+
+        words
+    }
+
+    // removes words seen less than `min_count` times, indices of the rest are compacted
+    // keeping their order
+    pub fn prune_min_count(&mut self, min_count: u64) {
+        let removed: Vec<String> = self.words.iter()
+            .zip(self.counts.iter())
+            .filter(|&(_, &count)| count < min_count)
+            .map(|(word, _)| word.clone())
+            .collect();
+
+        self.remove(removed);
+    }
+
+    // keeps the `max_size` most common words, indices are compacted keeping their order
+    pub fn prune_max_size(&mut self, max_size: usize) {
+        if self.len() <= max_size {
+            return;
+        }
+
+        let removed: Vec<String> = self.most_common(self.len())
+            .into_iter()
+            .skip(max_size)
+            .map(|(word, _)| word.to_string())
+            .collect();
+
+        self.remove(removed);
+    }
+
+    pub fn contains(&self, word: &str) -> bool
     {
         self.data.contains_key(word)
     }
 
-    pub fn word_index<'a>(&self, word: &'a str) -> Option<i64> {
-        match self.data.get(word) {
-            Some(&index) => Some(index),
-            None => None,
+    pub fn word_index(&self, word: &str) -> Option<i64> {
+        self.data.get(word).copied()
+    }
+
+    pub fn word(&self, index: i64) -> Option<&str> {
+        if index < 0 {
+            return None;
         }
+
+        self.words.get(index as usize).map(|word| word.as_str())
+    }
+
+    pub fn count(&self, index: i64) -> Option<u64> {
+        if index < 0 {
+            return None;
+        }
+
+        self.counts.get(index as usize).copied()
+    }
+
+    // words in index order
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn word_count(&self, word: &str) -> Option<u64> {
+        self.data.get(word).map(|&index| self.counts[index as usize])
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
     }
 
     pub fn len(&self) -> usize {
@@ -69,8 +187,39 @@ impl Dictionary {
     fn index_inc(&self) -> i64 {
         self.index.fetch_add(1, Ordering::SeqCst)
     }
+
+    fn remove(&mut self, words: Vec<String>) {
+        for word in words.iter() {
+            self.data.remove(word);
+        }
+
+        let order: Vec<(String, u64)> = self.words.iter()
+            .zip(self.counts.iter())
+            .filter(|&(word, _)| self.data.contains_key(word.as_str()))
+            .map(|(word, &count)| (word.clone(), count))
+            .collect();
+
+        self.assign(order);
+    }
+
+    // indices follow the order of `entries` of (word, count)
+    fn assign(&mut self, entries: Vec<(String, u64)>) {
+        let len = entries.len() as i64;
+
+        self.words.clear();
+        self.counts.clear();
+
+        for (index, (word, count)) in entries.into_iter().enumerate() {
+            self.data.insert(word.clone(), index as i64);
+            self.words.push(word);
+            self.counts.push(count);
+        }
+
+        self.index.store(len, Ordering::SeqCst);
+    }
 }
 
+// words and indices are compared, counts are not
 impl PartialEq for Dictionary {
     fn eq(&self, other: &Dictionary) -> bool {
         self.data == other.data
@@ -107,10 +256,15 @@ impl Dictionary {
         dict
     }
 
+    // counts of common words are summed
     pub fn join(&self, other: &Dictionary) -> Dictionary {
-        let mut dict = Dictionary::with_extend(self.iter().cloned());
+        let mut dict = Dictionary::default();
+
+        let entries = self.words.iter().zip(self.counts.iter());
 
-        dict.extend(other.iter().cloned());
+        for (word, &count) in entries.chain(other.words.iter().zip(other.counts.iter())) {
+            dict.insert_count(word, count);
+        }
 
         dict.reindex();
 
@@ -118,7 +272,145 @@ impl Dictionary {
     }
 }
 
+impl Dictionary {
+    // a line per word in index order: word<TAB>index<TAB>count
+    pub fn save_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        for (word, index, count) in self.entries() {
+            if word.contains(['\t', '\n', '\r']) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "word contains a separator"));
+            }
+
+            writeln!(writer, "{}\t{}\t{}", word, index, count)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_text<R>(reader: R) -> io::Result<Dictionary>
+        where R: BufRead
+    {
+        let mut entries: Vec<(String, i64, u64)> = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+
+            if fields.len() != 3 {
+                return Err(invalid("wrong dictionary line"));
+            }
+
+            let index = fields[1].parse().map_err(|_| invalid("wrong word index"))?;
+            let count = fields[2].parse().map_err(|_| invalid("wrong word count"))?;
+
+            entries.push((fields[0].to_string(), index, count));
+        }
+
+        Dictionary::with_entries(entries)
+    }
+
+    // magic, words count, then in index order: word length, word bytes, count;
+    // integers are little-endian, the index is the position
+    pub fn save_binary<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writer.write_all(BINARY_MAGIC)?;
+        writer.write_all(&(self.len() as u64).to_le_bytes())?;
+
+        for (word, _, count) in self.entries() {
+            writer.write_all(&(word.len() as u32).to_le_bytes())?;
+            writer.write_all(word.as_bytes())?;
+            writer.write_all(&count.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_binary<R>(mut reader: R) -> io::Result<Dictionary>
+        where R: Read
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != BINARY_MAGIC {
+            return Err(invalid("wrong dictionary format"));
+        }
+
+        let mut u32_buf = [0u8; 4];
+        let mut u64_buf = [0u8; 8];
+
+        reader.read_exact(&mut u64_buf)?;
+        let len = u64::from_le_bytes(u64_buf);
+
+        let mut entries: Vec<(String, i64, u64)> = Vec::new();
+
+        for index in 0..len {
+            reader.read_exact(&mut u32_buf)?;
+
+            let word = read_bytes(&mut reader, u32::from_le_bytes(u32_buf) as usize)?;
+
+            let word = String::from_utf8(word).map_err(|_| invalid("wrong word encoding"))?;
+
+            reader.read_exact(&mut u64_buf)?;
+
+            entries.push((word, index as i64, u64::from_le_bytes(u64_buf)));
+        }
+
+        Dictionary::with_entries(entries)
+    }
+
+    // (word, index, count) in index order
+    fn entries(&self) -> Vec<(&str, i64, u64)> {
+        self.iter_indexed()
+            .zip(self.counts.iter())
+            .map(|((index, word), &count)| (word, index, count))
+            .collect()
+    }
+
+    // words must be unique and indices must be a permutation of 0..len
+    fn with_entries(entries: Vec<(String, i64, u64)>) -> io::Result<Dictionary> {
+        let mut dict = Dictionary::default();
+        let mut seen: Vec<bool> = vec![false; entries.len()];
+
+        dict.words.resize(entries.len(), String::new());
+        dict.counts.resize(entries.len(), 0);
+
+        for (word, index, count) in entries {
+            if index < 0 || index as usize >= seen.len() {
+                return Err(invalid("word index out of range"));
+            }
+
+            if seen[index as usize] {
+                return Err(invalid("duplicated word index"));
+            }
+
+            if dict.contains(&word) {
+                return Err(invalid("duplicated word"));
+            }
+
+            seen[index as usize] = true;
+
+            dict.data.insert(word.clone(), index);
+            dict.words[index as usize] = word;
+            dict.counts[index as usize] = count;
+        }
+
+        dict.index.store(seen.len() as i64, Ordering::SeqCst);
+
+        Ok(dict)
+    }
+}
+
+// the assertions predate clippy, they are kept as they were written
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison, clippy::manual_unwrap_or, clippy::needless_borrows_for_generic_args)]
 mod testing {
     use super::*;
 
@@ -228,4 +520,153 @@ mod testing {
             assert_eq!(exist_index, expected_index, "check unknown index");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_dictionary_counts() {
+        let dict = Dictionary::with_extend(&["намело", "сугробы", "намело", "у", "намело", "сугробы"]);
+
+        assert_eq!(dict.word_count("намело"), Some(3), "check count");
+        assert_eq!(dict.word_count("у"), Some(1), "check single count");
+        assert_eq!(dict.word_count("крыльца"), None, "check unknown count");
+        assert_eq!(dict.total(), 6, "check total");
+
+        assert_eq!(dict.most_common(2), [("намело", 3), ("сугробы", 2)], "check most common");
+        assert_eq!(dict.most_common(10).len(), 3, "check most common overflow");
+    }
+
+    #[test]
+    fn test_dictionary_reindex_by_frequency() {
+        let mut dict = Dictionary::with_extend(&["у", "крыльца", "сугробы", "намело", "сугробы"]);
+
+        dict.reindex_by_frequency();
+
+        // ties keep the alphabetical order
+        assert_eq!(dict.word_index("сугробы"), Some(0), "check most frequent");
+        assert_eq!(dict.word_index("крыльца"), Some(1), "check tie");
+        assert_eq!(dict.word_index("намело"), Some(2), "check tie");
+        assert_eq!(dict.word_index("у"), Some(3), "check tie");
+
+        dict.insert("стужа");
+        assert_eq!(dict.word_index("стужа"), Some(4), "check next index");
+    }
+
+    #[test]
+    fn test_dictionary_prune() {
+        let words = ["намело", "сугробы", "намело", "у", "намело", "сугробы", "крыльца"];
+
+        let mut dict = Dictionary::with_extend(&words);
+        dict.prune_min_count(2);
+
+        assert_eq!(dict, Dictionary::with_extend(&["намело", "сугробы"]), "check min count");
+
+        let mut dict = Dictionary::with_extend(&words);
+        dict.prune_max_size(3);
+
+        assert_eq!(dict.len(), 3, "check max size");
+        assert!(!dict.contains("у"), "check pruned word");
+        assert_eq!(dict.word_index("крыльца"), Some(0), "check compacted index");
+        assert_eq!(dict.word_index("сугробы"), Some(2), "check compacted index");
+        assert_eq!(dict.word_count("намело"), Some(3), "check kept count");
+    }
+
+    #[test]
+    fn test_dictionary_join_counts() {
+        let dict = Dictionary::with_extend(&["намело", "сугробы", "намело"]);
+        let dict2 = Dictionary::with_extend(&["намело", "стужа"]);
+
+        let exist = dict.join(&dict2);
+
+        assert_eq!(exist.word_count("намело"), Some(3), "check merged count");
+        assert_eq!(exist.word_count("стужа"), Some(1), "check other count");
+        assert_eq!(exist.total(), 5, "check total");
+    }
+
+    #[test]
+    fn test_dictionary_save_load() {
+        let mut dict = Dictionary::with_extend(&["намело", "сугробы", "намело", "у", "крыльца", "намело"]);
+        dict.reindex_by_frequency();
+
+        let mut text: Vec<u8> = Vec::new();
+        dict.save_text(&mut text).unwrap();
+
+        assert!(String::from_utf8(text.clone()).unwrap().starts_with("намело\t0\t3\n"), "check text format");
+
+        let exist = Dictionary::load_text(text.as_slice()).unwrap();
+
+        assert_eq!(exist, dict, "check text round trip");
+        assert_eq!(exist.word_count("намело"), Some(3), "check text count");
+
+        let mut binary: Vec<u8> = Vec::new();
+        dict.save_binary(&mut binary).unwrap();
+
+        let exist = Dictionary::load_binary(binary.as_slice()).unwrap();
+
+        assert_eq!(exist, dict, "check binary round trip");
+        assert_eq!(exist.word_count("у"), Some(1), "check binary count");
+        assert_eq!(exist.most_common(4), dict.most_common(4), "check binary counts");
+
+        let mut next = exist;
+        next.insert("стужа");
+        assert_eq!(next.word_index("стужа"), Some(4), "check next index after load");
+
+        assert!(Dictionary::load_binary(&binary[..binary.len() - 1]).is_err(), "check truncated");
+        assert!(Dictionary::load_binary(&text[..]).is_err(), "check magic");
+
+        let mut corrupted = binary.clone();
+        corrupted[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Dictionary::load_binary(corrupted.as_slice()).is_err(), "check corrupted word length");
+    }
+
+    #[test]
+    fn test_dictionary_load_validation() {
+        assert!(Dictionary::load_text("намело\t0\t1\nсугробы\t2\t1\n".as_bytes()).is_err(), "check dense");
+        assert!(Dictionary::load_text("намело\t0\t1\nсугробы\t0\t1\n".as_bytes()).is_err(), "check unique index");
+        assert!(Dictionary::load_text("намело\t0\t1\nнамело\t1\t1\n".as_bytes()).is_err(), "check unique word");
+        assert!(Dictionary::load_text("намело\t-1\t1\n".as_bytes()).is_err(), "check negative index");
+        assert!(Dictionary::load_text("намело 0 1\n".as_bytes()).is_err(), "check separator");
+
+        let dict = Dictionary::load_text("сугробы\t1\t2\nнамело\t0\t5\n".as_bytes()).unwrap();
+
+        assert_eq!(dict.word_index("сугробы"), Some(1), "check loaded index");
+        assert_eq!(dict.word_count("намело"), Some(5), "check loaded count");
+
+        let dict = Dictionary::load_text("сугробы\t1\t2\r\nнамело\t0\t5\r\n".as_bytes()).unwrap();
+
+        assert_eq!(dict.word_count("сугробы"), Some(2), "check crlf count");
+    }
+
+    #[test]
+    fn test_dictionary_reverse_index() {
+        let mut dict = Dictionary::default();
+        dict.insert("сугробы");
+        dict.insert("намело");
+        dict.insert("сугробы");
+
+        assert_eq!(dict.word(0), Some("сугробы"), "check insert order");
+        assert_eq!(dict.word(1), Some("намело"), "check insert order");
+        assert_eq!(dict.word(2), None, "check out of range");
+        assert_eq!(dict.word(-1), None, "check negative index");
+
+        dict.reindex();
+        assert_eq!(dict.word(0), Some("намело"), "check reindex");
+
+        let joined = dict.join(&Dictionary::with_extend(&["у", "крыльца"]));
+
+        for (index, word) in joined.iter_indexed() {
+            assert_eq!(joined.word_index(word), Some(index), "check consistency of {}", word);
+        }
+
+        let words: Vec<&str> = joined.iter_indexed().map(|(_, word)| word).collect();
+        assert_eq!(words, ["крыльца", "намело", "сугробы", "у"], "check index order");
+
+        let mut dict = Dictionary::with_extend(&["у", "крыльца", "у", "намело", "крыльца", "у"]);
+        dict.reindex_by_frequency();
+        dict.prune_max_size(2);
+
+        let words: Vec<(i64, &str)> = dict.iter_indexed().collect();
+        assert_eq!(words, [(0, "у"), (1, "крыльца")], "check frequency order");
+        assert_eq!(dict.words(), ["у", "крыльца"], "check words");
+        assert_eq!(dict.count(1), Some(2), "check count by index");
+        assert_eq!(dict.count(2), None, "check count out of range");
+    }
+}
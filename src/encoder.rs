@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use kernels;
+use sif::Sif;
+use {SentenceEncoder, WordVectorModel};
+
+pub struct Mean;
+
+pub struct Sum;
+
+pub struct MaxPooling;
+
+pub struct MinMaxPooling;
+
+pub struct FrequencyWeighted {
+    weights: BTreeMap<String, f32>,
+    default_weight: f32,
+}
+
+fn doc_vectors<'a>(model: &'a dyn WordVectorModel, doc: &'a [String]) -> impl Iterator<Item=Vec<f32>> + 'a {
+    doc.iter()
+        .filter_map(move |word| model.word_to_vector(word))
+}
+
+fn pooling<F>(model: &dyn WordVectorModel, doc: &[String], select: F) -> Option<Vec<f32>>
+    where F: Fn(f32, f32) -> f32
+{
+    doc_vectors(model, doc).fold(None, |acc: Option<Vec<f32>>, vector| match acc {
+        None => Some(vector),
+        Some(mut acc) => {
+            acc.iter_mut()
+                .zip(vector.iter())
+                .for_each(|(v1, &v2)| *v1 = select(*v1, v2));
+
+            Some(acc)
+        }
+    })
+}
+
+impl SentenceEncoder for Sum {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        pooling(model, doc, |v1, v2| v1 + v2)
+    }
+}
+
+impl SentenceEncoder for Mean {
+    // divided by the number of vectors summed, subword vectors of unknown words included
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        let mut count = 0;
+        let mut sum: Option<Vec<f32>> = None;
+
+        for vector in doc_vectors(model, doc) {
+            match sum {
+                Some(ref mut acc) => kernels::axpy(1.0, &vector, acc),
+                None => sum = Some(vector),
+            }
+
+            count += 1;
+        }
+
+        let mut mean = sum?;
+
+        mean.iter_mut()
+            .for_each(|v| *v /= count as f32);
+
+        Some(mean)
+    }
+}
+
+impl SentenceEncoder for MaxPooling {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        pooling(model, doc, f32::max)
+    }
+}
+
+impl SentenceEncoder for MinMaxPooling {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        let mut min_max = pooling(model, doc, f32::min)?;
+
+        min_max.extend(pooling(model, doc, f32::max)?);
+
+        Some(min_max)
+    }
+}
+
+impl FrequencyWeighted {
+    pub fn with_weights<I, T>(weights: I, default_weight: f32) -> FrequencyWeighted
+        where
+            I: IntoIterator<Item=(T, f32)>,
+            T: ToString
+    {
+        FrequencyWeighted {
+            weights: weights.into_iter()
+                .map(|(word, weight)| (word.to_string(), weight))
+                .collect(),
+            default_weight,
+        }
+    }
+
+    // inverse document frequency over a reference corpus, unseen words get the highest weight
+    pub fn with_corpus<T>(corpus: &[Vec<T>]) -> FrequencyWeighted
+        where T: ToString
+    {
+        let mut frequency: BTreeMap<String, usize> = BTreeMap::new();
+
+        for doc in corpus {
+            let words: BTreeSet<String> = doc.iter().map(|word| word.to_string()).collect();
+
+            for word in words {
+                *frequency.entry(word).or_insert(0) += 1;
+            }
+        }
+
+        let docs_count = corpus.len() as f32;
+
+        FrequencyWeighted::with_weights(
+            frequency.into_iter()
+                .map(|(word, freq)| (word, (docs_count / freq as f32).ln())),
+            (docs_count + 1.0).ln(),
+        )
+    }
+
+    pub fn weight(&self, word: &str) -> f32 {
+        match self.weights.get(word) {
+            Some(&weight) => weight,
+            None => self.default_weight,
+        }
+    }
+}
+
+impl SentenceEncoder for FrequencyWeighted {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        let mut result: Option<Vec<f32>> = None;
+
+        for word in doc {
+            let vector = match model.word_to_vector(word) {
+                Some(vector) => vector,
+                None => continue,
+            };
+
+            let weight = self.weight(word);
+            let acc = result.get_or_insert_with(|| vec![0.0f32; vector.len()]);
+
+            kernels::axpy(weight, &vector, acc);
+        }
+
+        result
+    }
+}
+
+impl SentenceEncoder for Sif {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        self.embed(model, doc)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use model::Embeddings;
+    use subword::SubwordEmbeddings;
+    use testing::model::TestModel;
+
+    fn doc(text: &str) -> Vec<String> {
+        text.split_whitespace().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_encoder_sum_mean() {
+        let model = TestModel::default();
+
+        let sum = Sum.encode(&model, &doc("намело сугробы за калиткой")).unwrap();
+        let mean = Mean.encode(&model, &doc("намело сугробы за калиткой")).unwrap();
+
+        assert_eq!(sum.len(), 10, "check dimension");
+        assert_eq!(sum[0], 0.09620774f32 + 0.536739, "check sum");
+        assert_eq!(mean[0], (0.09620774f32 + 0.536739) / 2.0, "check mean over known words");
+
+        assert!(Mean.encode(&model, &doc("за калиткой")).is_none(), "check unknown doc");
+    }
+
+    #[test]
+    fn test_encoder_mean_subwords() {
+        let words = Embeddings::with_vectors(&["снег"], 2, vec![1.0, 1.0]).unwrap();
+        let model = SubwordEmbeddings::new(words, vec![3.0f32; 2 * 8], 3, 3).unwrap();
+
+        let known = model.word_to_vector("снег").unwrap();
+        let unknown = model.word_to_vector("снега").unwrap();
+
+        let mean = Mean.encode(&model, &doc("снег снега")).unwrap();
+        assert_eq!(mean[0], (known[0] + unknown[0]) / 2.0, "check mean over subword vectors");
+    }
+
+    #[test]
+    fn test_encoder_pooling() {
+        let model = TestModel::default();
+
+        let max = MaxPooling.encode(&model, &doc("намело сугробы")).unwrap();
+        assert_eq!(max[0], 0.536739f32, "check max pooling");
+        assert_eq!(max[1], 0.57043445f32, "check max pooling");
+
+        let min_max = MinMaxPooling.encode(&model, &doc("намело сугробы")).unwrap();
+        assert_eq!(min_max.len(), 20, "check min max dimension");
+        assert_eq!(min_max[0], 0.09620774f32, "check min pooling");
+        assert_eq!(min_max[10], 0.536739f32, "check max pooling");
+    }
+
+    #[test]
+    fn test_encoder_frequency_weighted() {
+        let model = TestModel::default();
+
+        let corpus = vec![
+            vec!["намело", "сугробы"],
+            vec!["сугробы", "у", "крыльца"],
+        ];
+
+        let encoder = FrequencyWeighted::with_corpus(&corpus);
+
+        assert_eq!(encoder.weight("сугробы"), 0.0, "check common word weight");
+        assert_eq!(encoder.weight("намело"), 2.0f32.ln(), "check rare word weight");
+
+        let exist = encoder.encode(&model, &doc("намело сугробы")).unwrap();
+        assert_eq!(exist[0], 0.09620774f32 * 2.0f32.ln(), "check weighted encode");
+    }
+}
@@ -0,0 +1,522 @@
+// Vectorized f32 kernels, the instruction set is picked at runtime.
+// Slices of different lengths are processed up to the shorter one, as `zip` does.
+// `norm` is not a kernel of its own, it is the square root of the `dot` kernel
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Scalar,
+    Sse2,
+    // AVX2 with FMA
+    Avx2,
+    Avx512,
+}
+
+// the best instruction set of the CPU, the detection is cached by std
+pub fn detected() -> Level {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            Level::Avx512
+        } else if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            Level::Avx2
+        } else {
+            Level::Sse2
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        Level::Scalar
+    }
+}
+
+// levels above the detected one fall back to it
+macro_rules! dispatch {
+    ($level:expr, $name:ident($($arg:expr),*)) => {{
+        #[cfg(target_arch = "x86_64")]
+        {
+            match $level.min(detected()) {
+                Level::Avx512 => unsafe { x86::$name::avx512($($arg),*) },
+                Level::Avx2 => unsafe { x86::$name::avx2($($arg),*) },
+                Level::Sse2 => unsafe { x86::$name::sse2($($arg),*) },
+                Level::Scalar => scalar::$name($($arg),*),
+            }
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        {
+            let _ = $level;
+
+            scalar::$name($($arg),*)
+        }
+    }};
+}
+
+pub fn dot(vec1: &[f32], vec2: &[f32]) -> f32 {
+    dot_with(detected(), vec1, vec2)
+}
+
+pub fn squared_l2(vec1: &[f32], vec2: &[f32]) -> f32 {
+    squared_l2_with(detected(), vec1, vec2)
+}
+
+// sum of absolute differences
+pub fn l1(vec1: &[f32], vec2: &[f32]) -> f32 {
+    l1_with(detected(), vec1, vec2)
+}
+
+pub fn norm(vec: &[f32]) -> f32 {
+    dot(vec, vec).sqrt()
+}
+
+pub fn sum(vec: &[f32]) -> f32 {
+    sum_with(detected(), vec)
+}
+
+// y += a * x
+pub fn axpy(a: f32, x: &[f32], y: &mut [f32]) {
+    axpy_with(detected(), a, x, y)
+}
+
+pub fn dot_with(level: Level, vec1: &[f32], vec2: &[f32]) -> f32 {
+    let len = vec1.len().min(vec2.len());
+
+    dispatch!(level, dot(&vec1[..len], &vec2[..len]))
+}
+
+pub fn squared_l2_with(level: Level, vec1: &[f32], vec2: &[f32]) -> f32 {
+    let len = vec1.len().min(vec2.len());
+
+    dispatch!(level, squared_l2(&vec1[..len], &vec2[..len]))
+}
+
+pub fn l1_with(level: Level, vec1: &[f32], vec2: &[f32]) -> f32 {
+    let len = vec1.len().min(vec2.len());
+
+    dispatch!(level, l1(&vec1[..len], &vec2[..len]))
+}
+
+pub fn sum_with(level: Level, vec: &[f32]) -> f32 {
+    dispatch!(level, sum(vec))
+}
+
+pub fn axpy_with(level: Level, a: f32, x: &[f32], y: &mut [f32]) {
+    let len = x.len().min(y.len());
+
+    dispatch!(level, axpy(a, &x[..len], &mut y[..len]))
+}
+
+mod scalar {
+    pub fn dot(vec1: &[f32], vec2: &[f32]) -> f32 {
+        vec1.iter()
+            .zip(vec2.iter())
+            .map(|(v1, v2)| v1 * v2)
+            .sum()
+    }
+
+    pub fn squared_l2(vec1: &[f32], vec2: &[f32]) -> f32 {
+        vec1.iter()
+            .zip(vec2.iter())
+            .map(|(v1, v2)| (v1 - v2) * (v1 - v2))
+            .sum()
+    }
+
+    pub fn l1(vec1: &[f32], vec2: &[f32]) -> f32 {
+        vec1.iter()
+            .zip(vec2.iter())
+            .map(|(v1, v2)| (v1 - v2).abs())
+            .sum()
+    }
+
+    pub fn sum(vec: &[f32]) -> f32 {
+        vec.iter().sum()
+    }
+
+    pub fn axpy(a: f32, x: &[f32], y: &mut [f32]) {
+        y.iter_mut()
+            .zip(x.iter())
+            .for_each(|(y, x)| *y += a * x);
+    }
+}
+
+// Every kernel has a variant per instruction set, slices have equal lengths,
+// the tail shorter than a register goes through the scalar code
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    pub mod dot {
+        use std::arch::x86_64::*;
+        use super::super::scalar;
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn sse2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 4 * 4;
+            let mut acc = _mm_setzero_ps();
+
+            for i in (0..body).step_by(4) {
+                acc = _mm_add_ps(acc, _mm_mul_ps(_mm_loadu_ps(vec1.as_ptr().add(i)), _mm_loadu_ps(vec2.as_ptr().add(i))));
+            }
+
+            let mut lanes = [0.0f32; 4];
+            _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::dot(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx2,fma")]
+        pub unsafe fn avx2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 8 * 8;
+            let mut acc = _mm256_setzero_ps();
+
+            for i in (0..body).step_by(8) {
+                acc = _mm256_fmadd_ps(_mm256_loadu_ps(vec1.as_ptr().add(i)), _mm256_loadu_ps(vec2.as_ptr().add(i)), acc);
+            }
+
+            let mut lanes = [0.0f32; 8];
+            _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::dot(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx512f")]
+        pub unsafe fn avx512(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 16 * 16;
+            let mut acc = _mm512_setzero_ps();
+
+            for i in (0..body).step_by(16) {
+                acc = _mm512_fmadd_ps(_mm512_loadu_ps(vec1.as_ptr().add(i)), _mm512_loadu_ps(vec2.as_ptr().add(i)), acc);
+            }
+
+            _mm512_reduce_add_ps(acc) + scalar::dot(&vec1[body..], &vec2[body..])
+        }
+    }
+
+    pub mod squared_l2 {
+        use std::arch::x86_64::*;
+        use super::super::scalar;
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn sse2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 4 * 4;
+            let mut acc = _mm_setzero_ps();
+
+            for i in (0..body).step_by(4) {
+                let diff = _mm_sub_ps(_mm_loadu_ps(vec1.as_ptr().add(i)), _mm_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm_add_ps(acc, _mm_mul_ps(diff, diff));
+            }
+
+            let mut lanes = [0.0f32; 4];
+            _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::squared_l2(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx2,fma")]
+        pub unsafe fn avx2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 8 * 8;
+            let mut acc = _mm256_setzero_ps();
+
+            for i in (0..body).step_by(8) {
+                let diff = _mm256_sub_ps(_mm256_loadu_ps(vec1.as_ptr().add(i)), _mm256_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm256_fmadd_ps(diff, diff, acc);
+            }
+
+            let mut lanes = [0.0f32; 8];
+            _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::squared_l2(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx512f")]
+        pub unsafe fn avx512(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 16 * 16;
+            let mut acc = _mm512_setzero_ps();
+
+            for i in (0..body).step_by(16) {
+                let diff = _mm512_sub_ps(_mm512_loadu_ps(vec1.as_ptr().add(i)), _mm512_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm512_fmadd_ps(diff, diff, acc);
+            }
+
+            _mm512_reduce_add_ps(acc) + scalar::squared_l2(&vec1[body..], &vec2[body..])
+        }
+    }
+
+    // the absolute value clears the sign bit
+    pub mod l1 {
+        use std::arch::x86_64::*;
+        use super::super::scalar;
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn sse2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 4 * 4;
+            let sign = _mm_set1_ps(-0.0);
+            let mut acc = _mm_setzero_ps();
+
+            for i in (0..body).step_by(4) {
+                let diff = _mm_sub_ps(_mm_loadu_ps(vec1.as_ptr().add(i)), _mm_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm_add_ps(acc, _mm_andnot_ps(sign, diff));
+            }
+
+            let mut lanes = [0.0f32; 4];
+            _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::l1(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx2,fma")]
+        pub unsafe fn avx2(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 8 * 8;
+            let sign = _mm256_set1_ps(-0.0);
+            let mut acc = _mm256_setzero_ps();
+
+            for i in (0..body).step_by(8) {
+                let diff = _mm256_sub_ps(_mm256_loadu_ps(vec1.as_ptr().add(i)), _mm256_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm256_add_ps(acc, _mm256_andnot_ps(sign, diff));
+            }
+
+            let mut lanes = [0.0f32; 8];
+            _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::l1(&vec1[body..], &vec2[body..])
+        }
+
+        #[target_feature(enable = "avx512f")]
+        pub unsafe fn avx512(vec1: &[f32], vec2: &[f32]) -> f32 {
+            let body = vec1.len() / 16 * 16;
+            let mut acc = _mm512_setzero_ps();
+
+            for i in (0..body).step_by(16) {
+                let diff = _mm512_sub_ps(_mm512_loadu_ps(vec1.as_ptr().add(i)), _mm512_loadu_ps(vec2.as_ptr().add(i)));
+                acc = _mm512_add_ps(acc, _mm512_abs_ps(diff));
+            }
+
+            _mm512_reduce_add_ps(acc) + scalar::l1(&vec1[body..], &vec2[body..])
+        }
+    }
+
+    pub mod sum {
+        use std::arch::x86_64::*;
+        use super::super::scalar;
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn sse2(vec: &[f32]) -> f32 {
+            let body = vec.len() / 4 * 4;
+            let mut acc = _mm_setzero_ps();
+
+            for i in (0..body).step_by(4) {
+                acc = _mm_add_ps(acc, _mm_loadu_ps(vec.as_ptr().add(i)));
+            }
+
+            let mut lanes = [0.0f32; 4];
+            _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::sum(&vec[body..])
+        }
+
+        #[target_feature(enable = "avx2,fma")]
+        pub unsafe fn avx2(vec: &[f32]) -> f32 {
+            let body = vec.len() / 8 * 8;
+            let mut acc = _mm256_setzero_ps();
+
+            for i in (0..body).step_by(8) {
+                acc = _mm256_add_ps(acc, _mm256_loadu_ps(vec.as_ptr().add(i)));
+            }
+
+            let mut lanes = [0.0f32; 8];
+            _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+
+            lanes.iter().sum::<f32>() + scalar::sum(&vec[body..])
+        }
+
+        #[target_feature(enable = "avx512f")]
+        pub unsafe fn avx512(vec: &[f32]) -> f32 {
+            let body = vec.len() / 16 * 16;
+            let mut acc = _mm512_setzero_ps();
+
+            for i in (0..body).step_by(16) {
+                acc = _mm512_add_ps(acc, _mm512_loadu_ps(vec.as_ptr().add(i)));
+            }
+
+            _mm512_reduce_add_ps(acc) + scalar::sum(&vec[body..])
+        }
+    }
+
+    pub mod axpy {
+        use std::arch::x86_64::*;
+        use super::super::scalar;
+
+        #[target_feature(enable = "sse2")]
+        pub unsafe fn sse2(a: f32, x: &[f32], y: &mut [f32]) {
+            let body = x.len() / 4 * 4;
+            let alpha = _mm_set1_ps(a);
+
+            for i in (0..body).step_by(4) {
+                let value = _mm_add_ps(_mm_loadu_ps(y.as_ptr().add(i)), _mm_mul_ps(alpha, _mm_loadu_ps(x.as_ptr().add(i))));
+                _mm_storeu_ps(y.as_mut_ptr().add(i), value);
+            }
+
+            scalar::axpy(a, &x[body..], &mut y[body..]);
+        }
+
+        #[target_feature(enable = "avx2,fma")]
+        pub unsafe fn avx2(a: f32, x: &[f32], y: &mut [f32]) {
+            let body = x.len() / 8 * 8;
+            let alpha = _mm256_set1_ps(a);
+
+            for i in (0..body).step_by(8) {
+                let value = _mm256_fmadd_ps(alpha, _mm256_loadu_ps(x.as_ptr().add(i)), _mm256_loadu_ps(y.as_ptr().add(i)));
+                _mm256_storeu_ps(y.as_mut_ptr().add(i), value);
+            }
+
+            scalar::axpy(a, &x[body..], &mut y[body..]);
+        }
+
+        #[target_feature(enable = "avx512f")]
+        pub unsafe fn avx512(a: f32, x: &[f32], y: &mut [f32]) {
+            let body = x.len() / 16 * 16;
+            let alpha = _mm512_set1_ps(a);
+
+            for i in (0..body).step_by(16) {
+                let value = _mm512_fmadd_ps(alpha, _mm512_loadu_ps(x.as_ptr().add(i)), _mm512_loadu_ps(y.as_ptr().add(i)));
+                _mm512_storeu_ps(y.as_mut_ptr().add(i), value);
+            }
+
+            scalar::axpy(a, &x[body..], &mut y[body..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    #[cfg(feature = "nightly")]
+    use test::{Bencher, black_box};
+
+    const LEVELS: [Level; 4] = [Level::Scalar, Level::Sse2, Level::Avx2, Level::Avx512];
+
+    fn vectors(len: usize) -> (Vec<f32>, Vec<f32>) {
+        let vec1: Vec<f32> = (0..len).map(|i| ((i * 7 % 13) as f32 - 6.0) / 8.0).collect();
+        let vec2: Vec<f32> = (0..len).map(|i| ((i * 5 % 11) as f32 - 5.0) / 4.0).collect();
+
+        (vec1, vec2)
+    }
+
+    fn close(value: f32, expected: f32) -> bool {
+        (value - expected).abs() <= 1e-4 * expected.abs().max(1.0)
+    }
+
+    #[test]
+    fn test_kernels() {
+        // lengths around every register width and its tails
+        for len in (0..40).chain(vec![299, 300, 301]) {
+            let (vec1, vec2) = vectors(len);
+
+            for &level in LEVELS.iter() {
+                assert!(close(dot_with(level, &vec1, &vec2), scalar::dot(&vec1, &vec2)), "check {:?} dot of {}", level, len);
+                assert!(close(squared_l2_with(level, &vec1, &vec2), scalar::squared_l2(&vec1, &vec2)), "check {:?} squared l2 of {}", level, len);
+                assert!(close(l1_with(level, &vec1, &vec2), scalar::l1(&vec1, &vec2)), "check {:?} l1 of {}", level, len);
+                assert!(close(sum_with(level, &vec1), scalar::sum(&vec1)), "check {:?} sum of {}", level, len);
+
+                let mut exist = vec2.clone();
+                let mut expected = vec2.clone();
+
+                axpy_with(level, 0.5, &vec1, &mut exist);
+                scalar::axpy(0.5, &vec1, &mut expected);
+
+                assert!(exist.iter().zip(expected.iter()).all(|(&v1, &v2)| close(v1, v2)), "check {:?} axpy of {}", level, len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels_lengths() {
+        assert_eq!(dot(&[1.0, 2.0, 3.0], &[2.0, 2.0]), 6.0, "check shorter slice");
+        assert_eq!(squared_l2(&[], &[1.0]), 0.0, "check empty slice");
+        assert_eq!(norm(&[3.0, 4.0]), 5.0, "check norm");
+
+        let mut y = vec![1.0f32; 2];
+        axpy(2.0, &[1.0, 2.0, 3.0], &mut y);
+        assert_eq!(y, [3.0, 5.0], "check shorter target");
+    }
+
+    #[cfg(feature = "nightly")]
+    fn bench_dot(bencher: &mut Bencher, level: Level) {
+        let (vec1, vec2) = vectors(300);
+
+        bencher.iter(|| dot_with(level, black_box(&vec1), black_box(&vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_dot_iterator(bencher: &mut Bencher) {
+        let (vec1, vec2) = vectors(300);
+
+        // the iterator chain the crate used before the kernels
+        bencher.iter(|| black_box(&vec1).iter().zip(black_box(&vec2).iter()).map(|(v1, v2)| v1 * v2).sum::<f32>());
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_dot_sse2(bencher: &mut Bencher) {
+        bench_dot(bencher, Level::Sse2);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_dot_avx2(bencher: &mut Bencher) {
+        bench_dot(bencher, Level::Avx2);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_dot_avx512(bencher: &mut Bencher) {
+        bench_dot(bencher, Level::Avx512);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_squared_l2_iterator(bencher: &mut Bencher) {
+        let (vec1, vec2) = vectors(300);
+
+        bencher.iter(|| scalar::squared_l2(black_box(&vec1), black_box(&vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_squared_l2(bencher: &mut Bencher) {
+        let (vec1, vec2) = vectors(300);
+
+        bencher.iter(|| squared_l2(black_box(&vec1), black_box(&vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_l1_iterator(bencher: &mut Bencher) {
+        let (vec1, vec2) = vectors(300);
+
+        bencher.iter(|| scalar::l1(black_box(&vec1), black_box(&vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_l1(bencher: &mut Bencher) {
+        let (vec1, vec2) = vectors(300);
+
+        bencher.iter(|| l1(black_box(&vec1), black_box(&vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_axpy_iterator(bencher: &mut Bencher) {
+        let (vec1, mut vec2) = vectors(300);
+
+        bencher.iter(|| scalar::axpy(0.5, black_box(&vec1), black_box(&mut vec2)));
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_axpy(bencher: &mut Bencher) {
+        let (vec1, mut vec2) = vectors(300);
+
+        bencher.iter(|| axpy(0.5, black_box(&vec1), black_box(&mut vec2)));
+    }
+}
@@ -1,25 +1,41 @@
-#![feature(test)]
-#![feature(core_slice_ext)]
-#![feature(universal_impl_trait)]
-#![feature(conservative_impl_trait)]
-#![feature(integer_atomics)]
-#![feature(entry_and_modify)]
-#![feature(vec_resize_default)]
+#![cfg_attr(all(test, feature = "nightly"), feature(test))]
 
 extern crate core;
+#[cfg(all(test, feature = "nightly"))]
 extern crate test;
+extern crate flate2;
+extern crate serde_json;
 
 pub mod dictionary;
 pub mod bow;
 pub mod wordvector;
 pub mod utils;
+pub mod sif;
+pub mod encoder;
+pub mod softcosine;
+pub mod metric;
+pub mod model;
+pub mod subword;
+pub mod train;
+pub mod corpus;
+pub mod transport;
+pub mod alignment;
+pub mod coverage;
+pub mod quantization;
+pub mod kernels;
+pub mod pairwise;
+pub mod matrix;
+pub mod vecops;
 
-mod matrix;
 mod testing;
 
 pub struct WordVector<'a> {
-    model: &'a WordVectorModel,
-    distance: &'a WordVectorDistance,
+    model: &'a dyn WordVectorModel,
+    distance: &'a dyn WordVectorDistance,
+    encoder: &'a dyn SentenceEncoder,
+    metric: &'a dyn WordMetric,
+    layout: CostLayout,
+    min_coverage: f32,
 }
 
 pub trait WordVectorModel {
@@ -28,6 +44,30 @@ pub trait WordVectorModel {
     fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>>;
 }
 
+// Layout of the ground distance matrix passed to WordVectorDistance, row-major
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CostLayout {
+    // square over the joined dictionary, only cells of doc1 word x doc2 word are filled
+    Partial,
+    // square over the joined dictionary, every cell is filled and symmetric
+    Symmetric,
+    // rectangular doc_bow1.len() x doc_bow2.len(), histograms over each document dictionary
+    Compact,
+}
+
 pub trait WordVectorDistance {
-    fn calc(&self, doc_bow1: &[f32], doc_bow2: &[f32], distance_matrix: &[f32]) -> f32;
+    fn calc(&self, layout: CostLayout, doc_bow1: &[f32], doc_bow2: &[f32], distance_matrix: &[f32]) -> f32;
+}
+
+pub trait SentenceEncoder {
+    fn encode(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>>;
+}
+
+pub trait WordMetric {
+    fn distance(&self, vec1: &[f32], vec2: &[f32]) -> f32;
+
+    // rows of vectors1 x rows of vectors2 distances, both sets are row-major with `dim` values a row
+    fn distances(&self, vectors1: &[f32], vectors2: &[f32], dim: usize) -> matrix::Matrix<f32> {
+        pairwise::map(vectors1, vectors2, dim, |vec1, vec2| self.distance(vec1, vec2))
+    }
 }
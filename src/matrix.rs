@@ -1,49 +1,261 @@
+use std::iter::{self, StepBy};
+use std::ops::{Index, IndexMut};
+use std::slice::Iter;
+
+use kernels;
+use WordVectorModel;
+
+// Row-major rows x cols matrix, rows are slices of one buffer
+#[derive(Debug, Clone, PartialEq)]
 pub struct Matrix<T> {
     data: Vec<T>,
-    sz: usize,
+    rows: usize,
+    cols: usize,
 }
 
 impl<T> Matrix<T>
     where T: Clone + Default
 {
-    pub fn new(sz: usize) -> Matrix<T> {
-        let mut data: Vec<T> = Vec::new();
-        data.resize(sz * sz, T::default());
+    pub fn new(rows: usize, cols: usize) -> Matrix<T> {
+        Matrix {
+            data: vec![T::default(); rows * cols],
+            rows,
+            cols,
+        }
+    }
+
+    pub fn square(sz: usize) -> Matrix<T> {
+        Matrix::new(sz, sz)
+    }
+
+    pub fn transpose(&self) -> Matrix<T> {
+        let mut data: Vec<T> = Vec::with_capacity(self.data.len());
+
+        for j in 0..self.cols {
+            data.extend(self.column(j).cloned());
+        }
 
         Matrix {
             data,
-            sz,
+            rows: self.cols,
+            cols: self.rows,
+        }
+    }
+}
+
+impl<T> Matrix<T> {
+    pub fn from_vec(rows: usize, cols: usize, data: Vec<T>) -> Result<Matrix<T>, &'static str> {
+        if rows * cols != data.len() {
+            return Err("matrix size mismatch");
+        }
+
+        Ok(Matrix {
+            data,
+            rows,
+            cols,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        if row >= self.rows || col >= self.cols {
+            return None;
+        }
+
+        Some(&self.data[row * self.cols + col])
+    }
+
+    pub fn row(&self, row: usize) -> Option<&[T]> {
+        if row >= self.rows {
+            return None;
+        }
+
+        Some(&self.data[row * self.cols..(row + 1) * self.cols])
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> Option<&mut [T]> {
+        if row >= self.rows {
+            return None;
+        }
+
+        Some(&mut self.data[row * self.cols..(row + 1) * self.cols])
+    }
+
+    // an empty iterator for an out of range column
+    pub fn column<'a>(&'a self, col: usize) -> StepBy<Iter<'a, T>> {
+        let start = if col < self.cols { col } else { self.data.len() };
+
+        self.data[start..].iter().step_by(self.cols.max(1))
+    }
+
+    // yields rows() rows, each empty when there are no columns
+    pub fn iter_rows<'a>(&'a self) -> impl Iterator<Item=&'a [T]> + 'a {
+        (0..self.rows).map(move |row| &self.data[row * self.cols..(row + 1) * self.cols])
+    }
+
+    pub fn iter_rows_mut<'a>(&'a mut self) -> impl Iterator<Item=&'a mut [T]> + 'a {
+        let empty = if self.cols == 0 { self.rows } else { 0 };
+
+        self.data.chunks_mut(self.cols.max(1))
+            .chain(iter::repeat_with(<&mut [T]>::default).take(empty))
+    }
+
+    pub fn iter_columns<'a>(&'a self) -> impl Iterator<Item=StepBy<Iter<'a, T>>> + 'a {
+        (0..self.cols).map(move |col| self.column(col))
+    }
+
+    pub fn push_row(&mut self, row: &[T]) -> Result<(), &'static str>
+        where T: Clone
+    {
+        if row.len() != self.cols {
+            return Err("row size mismatch");
         }
+
+        self.data.extend_from_slice(row);
+        self.rows += 1;
+
+        Ok(())
     }
 
-    pub fn as_matrix_mut(&mut self) -> Vec<&mut [T]> {
-        self.data.chunks_mut(self.sz).collect()
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
     }
 
-    pub fn as_matrix(&self) -> Vec<&[T]> {
-        self.data.chunks(self.sz).collect()
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
     }
 
-    pub fn as_slice(&self) -> &[T] { self.data.as_slice() }
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
+
+impl Matrix<f32> {
+    // vectors of the words as rows, fails on a word out of the model
+    pub fn from_model<T>(model: &dyn WordVectorModel, words: &[T]) -> Result<Matrix<f32>, &'static str>
+        where T: ToString
+    {
+        let mut matrix: Option<Matrix<f32>> = None;
+
+        for word in words {
+            let vector = match model.word_to_vector(&word.to_string()) {
+                Some(vector) => vector,
+                None => return Err("unknown word"),
+            };
+
+            matrix.get_or_insert_with(|| Matrix::new(0, vector.len())).push_row(&vector)?;
+        }
+
+        Ok(matrix.unwrap_or_else(|| Matrix::new(0, 0)))
+    }
+
+    // rows of unit L2 norm, zero rows are kept
+    pub fn normalize_rows(&mut self) {
+        for row in self.iter_rows_mut() {
+            let norm = kernels::norm(row);
+
+            if norm > 0.0 {
+                row.iter_mut().for_each(|v| *v /= norm);
+            }
+        }
+    }
+}
+
+impl<T> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        assert!(row < self.rows && col < self.cols, "matrix index out of range");
+
+        &self.data[row * self.cols + col]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Matrix<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        assert!(row < self.rows && col < self.cols, "matrix index out of range");
+
+        &mut self.data[row * self.cols + col]
+    }
 }
 
 #[cfg(test)]
 mod testing {
     use super::*;
+    use testing::model::TestModel;
 
     #[test]
     fn test_matrix() {
-        let mut matrix: Matrix<f32> = Matrix::new(10);
+        let mut matrix: Matrix<f32> = Matrix::square(10);
 
-        {
-            let mut m = matrix.as_matrix_mut();
+        matrix[(3, 4)] = 0.15f32;
+        matrix.row_mut(2).unwrap()[5] = 15.1024f32;
 
-            m[3][4] = 0.15f32;
-            m[2][5] = 15.1024f32;
-        }
+        assert_eq!(matrix[(3, 4)], 0.15f32, "check item [3][4]");
+        assert_eq!(matrix.get(2, 5), Some(&15.1024f32), "check item [2][5]");
+        assert_eq!(matrix.get(10, 0), None, "check out of range");
+    }
+
+    #[test]
+    fn test_matrix_rectangular() {
+        let mut matrix = Matrix::from_vec(2, 3, vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+
+        assert_eq!(matrix.shape(), (2, 3), "check shape");
+        assert_eq!(matrix.row(1), Some(&[4.0f32, 5.0, 6.0][..]), "check row");
+        assert_eq!(matrix.iter_rows().count(), 2, "check rows");
+        assert_eq!(matrix.column(1).cloned().collect::<Vec<f32>>(), [2.0, 5.0], "check column");
+        assert_eq!(matrix.column(3).count(), 0, "check column out of range");
+        assert_eq!(matrix.iter_columns().map(|column| column.sum::<f32>()).collect::<Vec<f32>>(), [5.0, 7.0, 9.0], "check columns");
+
+        let transposed = matrix.transpose();
+        assert_eq!(transposed.shape(), (3, 2), "check transposed shape");
+        assert_eq!(transposed.as_slice(), [1.0, 4.0, 2.0, 5.0, 3.0, 6.0], "check transposed");
+        assert_eq!(transposed.transpose(), matrix, "check double transpose");
+
+        assert!(matrix.push_row(&[1.0]).is_err(), "check row size");
+        matrix.push_row(&[0.0, 0.0, 0.0]).unwrap();
+        matrix.normalize_rows();
 
-        let m = matrix.as_matrix();
-        assert_eq!(m[3][4], 0.15f32, "check item [3][4]");
-        assert_eq!(m[2][5], 15.1024f32, "check item [2][5]");
+        assert!((kernels::norm(matrix.row(0).unwrap()) - 1.0).abs() < 1e-6, "check unit row");
+        assert_eq!(matrix.row(2), Some(&[0.0f32, 0.0, 0.0][..]), "check zero row");
+
+        assert!(Matrix::from_vec(2, 2, vec![1.0f32]).is_err(), "check size mismatch");
+    }
+
+    #[test]
+    fn test_matrix_empty_columns() {
+        let mut matrix: Matrix<f32> = Matrix::new(5, 0);
+
+        assert_eq!(matrix.rows(), 5, "check rows");
+        assert!(matrix.iter_rows().all(|row| row.is_empty()), "check empty rows");
+        assert_eq!(matrix.iter_rows().count(), 5, "check row count");
+        assert_eq!(matrix.iter_rows_mut().count(), 5, "check mutable row count");
+        assert_eq!(matrix.iter_columns().count(), 0, "check columns");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_matrix_from_model() {
+        let model = TestModel::default();
+
+        let matrix = Matrix::from_model(&model, &["намело", "сугробы"]).unwrap();
+
+        assert_eq!(matrix.rows(), 2, "check rows");
+        assert_eq!(matrix.row(1).unwrap(), model.word_to_vector("сугробы").unwrap().as_slice(), "check row vector");
+        assert!(Matrix::from_model(&model, &["калитки"]).is_err(), "check unknown word");
+    }
+}
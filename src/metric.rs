@@ -0,0 +1,123 @@
+use std::f32::consts::PI;
+
+use kernels;
+use matrix::Matrix;
+use pairwise;
+use WordMetric;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Euclidean,
+    SquaredEuclidean,
+    Cosine,
+    Angular,
+    Manhattan,
+}
+
+fn squared_euclidean(vec1: &[f32], vec2: &[f32]) -> f32 {
+    kernels::squared_l2(vec1, vec2)
+}
+
+fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
+    let dot = kernels::dot(vec1, vec2);
+    let norm1 = kernels::norm(vec1);
+    let norm2 = kernels::norm(vec2);
+
+    if norm1 == 0.0 || norm2 == 0.0 {
+        return 0.0;
+    }
+
+    (dot / (norm1 * norm2)).clamp(-1.0, 1.0)
+}
+
+impl WordMetric for Metric {
+    fn distance(&self, vec1: &[f32], vec2: &[f32]) -> f32 {
+        match *self {
+            Metric::Euclidean => squared_euclidean(vec1, vec2).sqrt(),
+            Metric::SquaredEuclidean => squared_euclidean(vec1, vec2),
+            Metric::Cosine => 1.0 - cosine_similarity(vec1, vec2),
+            Metric::Angular => cosine_similarity(vec1, vec2).acos() / PI,
+            Metric::Manhattan => kernels::l1(vec1, vec2),
+        }
+    }
+
+    fn distances(&self, vectors1: &[f32], vectors2: &[f32], dim: usize) -> Matrix<f32> {
+        let from_similarity = |transform: fn(f32) -> f32| {
+            let mut distances = pairwise::cosine_similarity(vectors1, vectors2, dim);
+
+            distances.as_mut_slice().iter_mut().for_each(|v| *v = transform(*v));
+
+            distances
+        };
+
+        match *self {
+            Metric::Euclidean => pairwise::euclidean(vectors1, vectors2, dim),
+            Metric::SquaredEuclidean => pairwise::squared_euclidean(vectors1, vectors2, dim),
+            Metric::Cosine => from_similarity(|similarity| 1.0 - similarity),
+            Metric::Angular => from_similarity(|similarity| similarity.acos() / PI),
+            Metric::Manhattan => pairwise::map(vectors1, vectors2, dim, |vec1, vec2| self.distance(vec1, vec2)),
+        }
+    }
+}
+
+impl<F> WordMetric for F
+    where F: Fn(&[f32], &[f32]) -> f32
+{
+    fn distance(&self, vec1: &[f32], vec2: &[f32]) -> f32 {
+        self(vec1, vec2)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_metric_distance() {
+        let vec1 = [1.0f32, 0.0];
+        let vec2 = [0.0f32, 2.0];
+
+        assert_eq!(Metric::Euclidean.distance(&vec1, &vec2), 5.0f32.sqrt(), "check euclidean");
+        assert_eq!(Metric::SquaredEuclidean.distance(&vec1, &vec2), 5.0, "check squared euclidean");
+        assert_eq!(Metric::Cosine.distance(&vec1, &vec2), 1.0, "check cosine");
+        assert_eq!(Metric::Angular.distance(&vec1, &vec2), 0.5, "check angular");
+        assert_eq!(Metric::Manhattan.distance(&vec1, &vec2), 3.0, "check manhattan");
+
+        assert_eq!(Metric::Cosine.distance(&vec1, &[3.0, 0.0]), 0.0, "check cosine same direction");
+        assert_eq!(Metric::Angular.distance(&vec1, &[-1.0, 0.0]), 1.0, "check angular opposite");
+    }
+
+    #[test]
+    fn test_metric_closure() {
+        let chebyshev = |vec1: &[f32], vec2: &[f32]| -> f32 {
+            vec1.iter()
+                .zip(vec2.iter())
+                .fold(0.0f32, |acc, (v1, v2)| acc.max((v1 - v2).abs()))
+        };
+
+        assert_eq!(chebyshev.distance(&[1.0, 0.0], &[0.0, 2.0]), 2.0, "check closure metric");
+        assert_eq!(chebyshev.distances(&[1.0, 0.0, 0.0, 0.0], &[0.0, 2.0], 2).as_slice(), [2.0, 2.0], "check closure distances");
+    }
+
+    #[test]
+    fn test_metric_distances() {
+        let vectors1 = [1.0f32, 0.0, 3.0, 4.0, 0.0, 0.0];
+        let vectors2 = [0.0f32, 2.0, -1.0, 0.5];
+
+        let metrics = [Metric::Euclidean, Metric::SquaredEuclidean, Metric::Cosine, Metric::Angular, Metric::Manhattan];
+
+        for metric in metrics.iter() {
+            let exist = metric.distances(&vectors1, &vectors2, 2);
+
+            assert_eq!(exist.shape(), (3, 2), "check {:?} shape", metric);
+
+            for (i, vec1) in vectors1.chunks(2).enumerate() {
+                for (j, vec2) in vectors2.chunks(2).enumerate() {
+                    let expected = metric.distance(vec1, vec2);
+
+                    assert!((exist[(i, j)] - expected).abs() < 1e-5, "check {:?} distance [{}][{}]", metric, i, j);
+                }
+            }
+        }
+    }
+}
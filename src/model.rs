@@ -0,0 +1,348 @@
+use std::io::{self, BufRead, Write};
+
+use dictionary::Dictionary;
+use matrix::Matrix;
+use pairwise;
+use utils::{invalid, read_bytes, sentence_mean, smallest_k};
+use WordVectorModel;
+
+// queries of a nearest_batch distance matrix
+const QUERY_CHUNK: usize = 64;
+
+// Dense f32 word vectors, row-major in the dictionary index order
+#[derive(Debug)]
+pub struct Embeddings {
+    dict: Dictionary,
+    words: Vec<String>,
+    vectors: Matrix<f32>,
+}
+
+impl Embeddings {
+    pub fn new(dim: usize) -> Embeddings {
+        Embeddings {
+            dict: Dictionary::default(),
+            words: Vec::new(),
+            vectors: Matrix::new(0, dim),
+        }
+    }
+
+    pub fn with_vectors<T>(words: &[T], dim: usize, vectors: Vec<f32>) -> Result<Embeddings, &'static str>
+        where T: ToString
+    {
+        if words.len() * dim != vectors.len() {
+            return Err("vectors size mismatch");
+        }
+
+        let mut embeddings = Embeddings::new(dim);
+
+        for (word, vector) in words.iter().zip(vectors.chunks(dim.max(1))) {
+            embeddings.push(word.to_string(), vector)?;
+        }
+
+        Ok(embeddings)
+    }
+
+    pub fn push<S>(&mut self, word: S, vector: &[f32]) -> Result<(), &'static str>
+        where S: ToString
+    {
+        let word = word.to_string();
+
+        if vector.len() != self.dim() {
+            return Err("vector dimension mismatch");
+        }
+
+        if self.dict.contains(&word) {
+            return Err("duplicate word");
+        }
+
+        self.dict.insert(word.clone());
+        self.words.push(word);
+        self.vectors.push_row(vector)?;
+
+        Ok(())
+    }
+
+    pub fn dim(&self) -> usize {
+        self.vectors.cols()
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        self.vectors.as_slice()
+    }
+
+    // one row per word in the index order
+    pub fn matrix(&self) -> &Matrix<f32> {
+        &self.vectors
+    }
+
+    pub fn vector(&self, index: usize) -> Option<&[f32]> {
+        self.vectors.row(index)
+    }
+
+    pub fn word_vector(&self, word: &str) -> Option<&[f32]> {
+        let index = self.dict.word_index(word)?;
+
+        self.vector(index as usize)
+    }
+
+    // brute force by the squared euclidean distance
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        if query.len() != self.dim() {
+            return Vec::new();
+        }
+
+        self.nearest_batch(query, k).pop().unwrap_or_default()
+    }
+
+    // nearest of every row-major query, distances to all words are computed for
+    // QUERY_CHUNK queries at a time
+    pub fn nearest_batch(&self, queries: &[f32], k: usize) -> Vec<Vec<(usize, f32)>> {
+        let dim = self.dim();
+        if dim == 0 {
+            return Vec::new();
+        }
+
+        let mut nearest: Vec<Vec<(usize, f32)>> = Vec::with_capacity(queries.len() / dim);
+
+        for chunk in queries[..queries.len() - queries.len() % dim].chunks(QUERY_CHUNK * dim) {
+            let distances = pairwise::squared_euclidean(chunk, self.vectors.as_slice(), dim);
+
+            nearest.extend(distances.iter_rows()
+                .map(|row| smallest_k(row.iter().cloned().enumerate().collect(), k)));
+        }
+
+        nearest
+    }
+
+    // word2vec text format: "count dim" header, then "word v1 v2 ..." lines
+    pub fn save_text<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writeln!(writer, "{} {}", self.len(), self.dim())?;
+
+        for (index, word) in self.words.iter().enumerate() {
+            write!(writer, "{}", word)?;
+
+            for v in self.vector(index).unwrap_or(&[]) {
+                write!(writer, " {}", v)?;
+            }
+
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_text<R>(reader: R) -> io::Result<Embeddings>
+        where R: BufRead
+    {
+        let mut lines = reader.lines();
+
+        let (count, dim) = match lines.next() {
+            Some(line) => parse_header(&line?)?,
+            None => return Err(invalid("empty embeddings")),
+        };
+
+        let mut embeddings = Embeddings::new(dim);
+        let mut vector: Vec<f32> = Vec::with_capacity(dim);
+
+        for line in lines.take(count) {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+
+            let word = match fields.next() {
+                Some(word) => word,
+                None => return Err(invalid("empty embeddings line")),
+            };
+
+            vector.clear();
+            for v in fields {
+                vector.push(v.parse().map_err(|_| invalid("wrong vector value"))?);
+            }
+
+            embeddings.push(word, &vector).map_err(invalid)?;
+        }
+
+        if embeddings.len() != count {
+            return Err(invalid("unexpected end of embeddings"));
+        }
+
+        Ok(embeddings)
+    }
+
+    // word2vec binary format: "count dim" header, then "word " followed by dim little-endian f32
+    pub fn save_binary<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writeln!(writer, "{} {}", self.len(), self.dim())?;
+
+        for (index, word) in self.words.iter().enumerate() {
+            write!(writer, "{} ", word)?;
+
+            for v in self.vector(index).unwrap_or(&[]) {
+                writer.write_all(&v.to_bits().to_le_bytes())?;
+            }
+
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load_binary<R>(mut reader: R) -> io::Result<Embeddings>
+        where R: BufRead
+    {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+
+        let (count, dim) = parse_header(&header)?;
+
+        let mut embeddings = Embeddings::new(dim);
+        let size = dim.checked_mul(4).ok_or_else(|| invalid("wrong vector size"))?;
+        let mut vector: Vec<f32> = Vec::new();
+
+        for _ in 0..count {
+            let mut word: Vec<u8> = Vec::new();
+            reader.read_until(b' ', &mut word)?;
+
+            if word.pop() != Some(b' ') {
+                return Err(invalid("unexpected end of embeddings"));
+            }
+
+            let word = String::from_utf8(word)
+                .map_err(|_| invalid("wrong word encoding"))?;
+
+            let buffer = read_bytes(&mut reader, size)?;
+
+            vector.clear();
+            vector.extend(buffer.chunks(4)
+                .map(|bytes| f32::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))));
+
+            embeddings.push(word.trim_start(), &vector).map_err(invalid)?;
+        }
+
+        Ok(embeddings)
+    }
+}
+
+fn parse_header(line: &str) -> io::Result<(usize, usize)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+
+    if fields.len() != 2 {
+        return Err(invalid("wrong embeddings header"));
+    }
+
+    let count = fields[0].parse().map_err(|_| invalid("wrong words count"))?;
+    let dim = fields[1].parse().map_err(|_| invalid("wrong dimension"))?;
+
+    Ok((count, dim))
+}
+
+impl WordVectorModel for Embeddings {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.dict.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.word_vector(word).map(|vector| vector.to_vec())
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        sentence_mean(text, self.dim(), |word| self.word_vector(word))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    fn embeddings() -> Embeddings {
+        Embeddings::with_vectors(
+            &["намело", "сугробы", "у"],
+            2,
+            vec![0.5f32, -1.25, 2.0, 0.0, 1.0, 1.0]
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let exist = embeddings();
+
+        assert_eq!(exist.len(), 3, "check length");
+        assert_eq!(exist.word_index("сугробы"), Some(1), "check index");
+        assert_eq!(exist.word_to_vector("у"), Some(vec![1.0, 1.0]), "check vector");
+        assert_eq!(exist.sentence_to_vector("намело у за"), Some(vec![0.75, -0.125]), "check sentence vector");
+        assert_eq!(exist.sentence_to_vector("за калиткой"), None, "check unknown sentence");
+
+        assert!(Embeddings::with_vectors(&["у", "у"], 1, vec![1.0, 2.0]).is_err(), "check duplicate");
+        assert!(Embeddings::with_vectors(&["у"], 2, vec![1.0]).is_err(), "check size");
+    }
+
+    #[test]
+    fn test_embeddings_nearest() {
+        let exist = embeddings();
+
+        let nearest = exist.nearest(&[1.5, 0.5], 2);
+        assert_eq!(nearest.iter().map(|&(index, _)| index).collect::<Vec<usize>>(), [1, 2], "check nearest words");
+        assert!((nearest[0].1 - 0.5).abs() < 1e-6, "check nearest distance");
+
+        let batch = exist.nearest_batch(&[1.5, 0.5, 0.5, -1.0], 1);
+        assert_eq!(batch.len(), 2, "check batch size");
+        assert_eq!(batch[1][0].0, 0, "check second query");
+
+        let queries: Vec<f32> = (0..QUERY_CHUNK * 2 + 1).flat_map(|i| vec![i as f32, 1.0]).collect();
+        let batch = exist.nearest_batch(&queries, 3);
+        assert_eq!(batch.len(), QUERY_CHUNK * 2 + 1, "check chunked batch size");
+        assert_eq!(batch[QUERY_CHUNK * 2], exist.nearest(&[(QUERY_CHUNK * 2) as f32, 1.0], 3), "check last chunk");
+
+        assert!(exist.nearest(&[1.0], 1).is_empty(), "check query dimension");
+    }
+
+    #[test]
+    fn test_embeddings_text_format() {
+        let expected = embeddings();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        expected.save_text(&mut buffer).unwrap();
+
+        let exist = Embeddings::load_text(buffer.as_slice()).unwrap();
+
+        assert_eq!(exist.words(), expected.words(), "check words");
+        assert_eq!(exist.as_slice(), expected.as_slice(), "check vectors");
+
+        assert!(Embeddings::load_text("2 2\nу 1 1\n".as_bytes()).is_err(), "check truncated");
+        assert!(Embeddings::load_text("1 2\nу 1\n".as_bytes()).is_err(), "check dimension");
+    }
+
+    #[test]
+    fn test_embeddings_binary_format() {
+        let expected = embeddings();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        expected.save_binary(&mut buffer).unwrap();
+
+        let exist = Embeddings::load_binary(buffer.as_slice()).unwrap();
+
+        assert_eq!(exist.words(), expected.words(), "check words");
+        assert_eq!(exist.as_slice(), expected.as_slice(), "check vectors");
+
+        assert!(Embeddings::load_binary(&buffer[..buffer.len() - 4]).is_err(), "check truncated");
+        assert!(Embeddings::load_binary("1 4611686018427387903\nу ".as_bytes()).is_err(), "check corrupted dimension");
+    }
+}
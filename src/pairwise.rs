@@ -0,0 +1,153 @@
+// All distances between two sets of row-major vectors of `dim` values as one
+// rows1 x rows2 matrix. Euclidean and cosine distances come from the dot
+// products, ||a||² + ||b||² - 2a·b with the row norms computed once, and the
+// dot products are computed block by block so that a tile of both sets stays
+// in the cache
+
+use kernels;
+use matrix::Matrix;
+
+const BLOCK_ROWS: usize = 16;
+const BLOCK_COLS: usize = 64;
+
+fn rows(vectors: &[f32], dim: usize) -> usize {
+    vectors.len().checked_div(dim).unwrap_or(0)
+}
+
+// value(row1, row2) for every pair, a trailing partial row is ignored
+pub fn map<F>(vectors1: &[f32], vectors2: &[f32], dim: usize, value: F) -> Matrix<f32>
+    where F: Fn(&[f32], &[f32]) -> f32
+{
+    let (rows1, rows2) = (rows(vectors1, dim), rows(vectors2, dim));
+    let mut out: Matrix<f32> = Matrix::new(rows1, rows2);
+
+    for start1 in (0..rows1).step_by(BLOCK_ROWS) {
+        for start2 in (0..rows2).step_by(BLOCK_COLS) {
+            for i in start1..(start1 + BLOCK_ROWS).min(rows1) {
+                let row1 = &vectors1[i * dim..(i + 1) * dim];
+
+                for j in start2..(start2 + BLOCK_COLS).min(rows2) {
+                    out[(i, j)] = value(row1, &vectors2[j * dim..(j + 1) * dim]);
+                }
+            }
+        }
+    }
+
+    out
+}
+
+pub fn dot_products(vectors1: &[f32], vectors2: &[f32], dim: usize) -> Matrix<f32> {
+    map(vectors1, vectors2, dim, kernels::dot)
+}
+
+pub fn squared_norms(vectors: &[f32], dim: usize) -> Vec<f32> {
+    (0..rows(vectors, dim))
+        .map(|i| {
+            let row = &vectors[i * dim..(i + 1) * dim];
+
+            kernels::dot(row, row)
+        })
+        .collect()
+}
+
+// rounding of the expansion can go below zero for close vectors, it is clamped
+pub fn squared_euclidean(vectors1: &[f32], vectors2: &[f32], dim: usize) -> Matrix<f32> {
+    let norms1 = squared_norms(vectors1, dim);
+    let norms2 = squared_norms(vectors2, dim);
+
+    let mut out = dot_products(vectors1, vectors2, dim);
+
+    for (row, norm1) in out.iter_rows_mut().zip(norms1.iter()) {
+        for (v, norm2) in row.iter_mut().zip(norms2.iter()) {
+            *v = (norm1 + norm2 - 2.0 * *v).max(0.0);
+        }
+    }
+
+    out
+}
+
+pub fn euclidean(vectors1: &[f32], vectors2: &[f32], dim: usize) -> Matrix<f32> {
+    let mut out = squared_euclidean(vectors1, vectors2, dim);
+
+    out.as_mut_slice().iter_mut().for_each(|v| *v = v.sqrt());
+
+    out
+}
+
+// zero for a zero vector, clamped to [-1, 1]
+pub fn cosine_similarity(vectors1: &[f32], vectors2: &[f32], dim: usize) -> Matrix<f32> {
+    let norms1: Vec<f32> = squared_norms(vectors1, dim).iter().map(|v| v.sqrt()).collect();
+    let norms2: Vec<f32> = squared_norms(vectors2, dim).iter().map(|v| v.sqrt()).collect();
+
+    let mut out = dot_products(vectors1, vectors2, dim);
+
+    for (row, norm1) in out.iter_rows_mut().zip(norms1.iter()) {
+        for (v, norm2) in row.iter_mut().zip(norms2.iter()) {
+            *v = if *norm1 == 0.0 || *norm2 == 0.0 { 0.0 } else { (*v / (norm1 * norm2)).clamp(-1.0, 1.0) };
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    #[cfg(feature = "nightly")]
+    use test::{Bencher, black_box};
+    use quantization::scalar::testing::random_embeddings;
+
+    #[test]
+    fn test_pairwise() {
+        let vectors = random_embeddings(70, 13);
+        let (vectors1, vectors2) = vectors.as_slice().split_at(25 * 13);
+
+        let squared = squared_euclidean(vectors1, vectors2, 13);
+        let cosine = cosine_similarity(vectors1, vectors2, 13);
+
+        assert_eq!(squared.shape(), (25, 45), "check shape");
+
+        for i in 0..25 {
+            for j in 0..45 {
+                let (row1, row2) = (&vectors1[i * 13..(i + 1) * 13], &vectors2[j * 13..(j + 1) * 13]);
+
+                let expected = kernels::squared_l2(row1, row2);
+                assert!((squared[(i, j)] - expected).abs() < 1e-4, "check squared euclidean [{}][{}]", i, j);
+
+                let expected = kernels::dot(row1, row2) / (kernels::norm(row1) * kernels::norm(row2));
+                assert!((cosine[(i, j)] - expected).abs() < 1e-5, "check cosine [{}][{}]", i, j);
+            }
+        }
+
+        let same = euclidean(vectors1, vectors1, 13);
+        assert!((0..25).all(|i| same[(i, i)] == 0.0), "check zero self distance");
+
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0], 2).as_slice(), [0.0], "check zero vector");
+        assert!(dot_products(vectors1, vectors2, 0).is_empty(), "check zero dimension");
+
+        let close = [1.0e4f32, 1.0e4, 1.0e4 + 1.0e-3, 1.0e4];
+        assert!(squared_euclidean(&close[..2], &close[2..], 2)[(0, 0)] >= 0.0, "check clamped rounding");
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_pairwise_by_pair(bencher: &mut Bencher) {
+        let vectors = random_embeddings(200, 300);
+
+        bencher.iter(|| {
+            let vectors = black_box(vectors.as_slice());
+
+            (0..200)
+                .flat_map(|i| (0..200).map(move |j| kernels::squared_l2(&vectors[i * 300..(i + 1) * 300], &vectors[j * 300..(j + 1) * 300]).sqrt()))
+                .collect::<Vec<f32>>()
+        });
+    }
+
+    #[cfg(feature = "nightly")]
+    #[bench]
+    fn bench_pairwise_blocked(bencher: &mut Bencher) {
+        let vectors = random_embeddings(200, 300);
+
+        bencher.iter(|| euclidean(black_box(vectors.as_slice()), black_box(vectors.as_slice()), 300));
+    }
+}
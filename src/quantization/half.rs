@@ -0,0 +1,384 @@
+use std::io::{self, BufRead, Read, Write};
+
+use dictionary::Dictionary;
+use model::Embeddings;
+use quantization::{AccuracyReport, accuracy_report, dictionary_copy};
+use quantization::{check_magic, write_u64, read_u64, write_words, read_words};
+use utils::{invalid, read_bytes, sentence_mean, smallest_k, words_mean};
+use WordVectorModel;
+
+const MAGIC: &[u8; 4] = b"WVH1";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HalfFormat {
+    // IEEE 754 binary16: 5 exponent and 10 mantissa bits
+    F16,
+    // bfloat16: the f32 exponent and 7 mantissa bits
+    Bf16,
+}
+
+// round to nearest even, out of range values become infinities
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let man = bits & 0x7f_ffff;
+
+    if exp == 0xff {
+        return sign | 0x7c00 | if man != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exp = exp - 127 + 15;
+
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if half_exp <= 0 {
+        // subnormal half
+        if half_exp < -10 {
+            return sign;
+        }
+
+        let man = man | 0x80_0000;
+        let shift = (14 - half_exp) as u32;
+
+        let mut half = man >> shift;
+        let rest = man & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+
+        if rest > halfway || (rest == halfway && half & 1 == 1) {
+            half += 1;
+        }
+
+        return sign | half as u16;
+    }
+
+    let mut half = ((half_exp as u32) << 10) | (man >> 13);
+    let rest = man & 0x1fff;
+
+    // a carry into the exponent is the correct rounding
+    if rest > 0x1000 || (rest == 0x1000 && half & 1 == 1) {
+        half += 1;
+    }
+
+    sign | half as u16
+}
+
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exp = ((half >> 10) & 0x1f) as u32;
+    let man = (half & 0x3ff) as u32;
+
+    match exp {
+        0 => {
+            let value = man as f32 / 16_777_216.0;
+
+            if sign != 0 { -value } else { value }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (man << 13)),
+        _ => f32::from_bits(sign | ((exp + 112) << 23) | (man << 13)),
+    }
+}
+
+// round to nearest even
+pub fn f32_to_bf16(value: f32) -> u16 {
+    let bits = value.to_bits();
+
+    if value.is_nan() {
+        return ((bits >> 16) | 0x40) as u16;
+    }
+
+    let rounding = 0x7fff + ((bits >> 16) & 1);
+
+    (bits.wrapping_add(rounding) >> 16) as u16
+}
+
+pub fn bf16_to_f32(half: u16) -> f32 {
+    f32::from_bits((half as u32) << 16)
+}
+
+impl HalfFormat {
+    pub fn encode(self, value: f32) -> u16 {
+        match self {
+            HalfFormat::F16 => f32_to_f16(value),
+            HalfFormat::Bf16 => f32_to_bf16(value),
+        }
+    }
+
+    pub fn decode(self, half: u16) -> f32 {
+        match self {
+            HalfFormat::F16 => f16_to_f32(half),
+            HalfFormat::Bf16 => bf16_to_f32(half),
+        }
+    }
+}
+
+// 16-bit word vectors, decoded on access, every sum is accumulated in f32
+#[derive(Debug)]
+pub struct HalfEmbeddings {
+    dict: Dictionary,
+    dim: usize,
+    format: HalfFormat,
+    data: Vec<u16>,
+}
+
+impl HalfEmbeddings {
+    pub fn from_embeddings(embeddings: &Embeddings, format: HalfFormat) -> HalfEmbeddings {
+        HalfEmbeddings {
+            dict: dictionary_copy(embeddings.dictionary()),
+            dim: embeddings.dim(),
+            format,
+            data: embeddings.as_slice().iter().map(|&v| format.encode(v)).collect(),
+        }
+    }
+
+    pub fn to_embeddings(&self) -> Result<Embeddings, &'static str> {
+        let vectors: Vec<f32> = self.data.iter().map(|&half| self.format.decode(half)).collect();
+
+        Embeddings::with_vectors(self.dict.words(), self.dim, vectors)
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn format(&self) -> HalfFormat {
+        self.format
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    pub fn raw(&self, index: usize) -> Option<&[u16]> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(&self.data[index * self.dim..(index + 1) * self.dim])
+    }
+
+    pub fn vector(&self, index: usize) -> Option<Vec<f32>> {
+        Some(self.raw(index)?.iter().map(|&half| self.format.decode(half)).collect())
+    }
+
+    pub fn word_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.vector(self.dict.word_index(word)? as usize)
+    }
+
+    pub fn dot(&self, i: usize, j: usize) -> Option<f32> {
+        let (raw1, raw2) = (self.raw(i)?, self.raw(j)?);
+
+        Some(raw1.iter()
+            .zip(raw2.iter())
+            .map(|(&h1, &h2)| self.format.decode(h1) * self.format.decode(h2))
+            .sum())
+    }
+
+    pub fn squared_euclidean_query(&self, query: &[f32], index: usize) -> Option<f32> {
+        let raw = self.raw(index)?;
+
+        if query.len() != self.dim {
+            return None;
+        }
+
+        Some(query.iter()
+            .zip(raw.iter())
+            .map(|(q, &half)| {
+                let diff = q - self.format.decode(half);
+
+                diff * diff
+            })
+            .sum())
+    }
+
+    // brute force by the squared euclidean distance
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let distances: Vec<(usize, f32)> = (0..self.len())
+            .filter_map(|index| self.squared_euclidean_query(query, index).map(|distance| (index, distance)))
+            .collect();
+
+        smallest_k(distances, k)
+    }
+
+    // mean of the known words
+    pub fn centroid<T>(&self, words: &[T]) -> Option<Vec<f32>>
+        where T: ToString
+    {
+        words_mean(words.iter().map(|word| word.to_string()), self.dim, |word| self.word_vector(word))
+    }
+
+    pub fn accuracy(&self, original: &Embeddings, sample: usize) -> AccuracyReport {
+        accuracy_report(original, sample, |index| self.vector(index).unwrap_or_default())
+    }
+
+    // magic, format, dim, words, vectors; little-endian
+    pub fn save<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[match self.format {
+            HalfFormat::F16 => 0,
+            HalfFormat::Bf16 => 1,
+        }])?;
+        write_u64(writer, self.dim as u64)?;
+        write_words(writer, &self.dict)?;
+
+        for half in self.data.iter() {
+            writer.write_all(&half.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn load<R>(mut reader: R) -> io::Result<HalfEmbeddings>
+        where R: Read
+    {
+        check_magic(&mut reader, MAGIC)?;
+
+        let mut format = [0u8; 1];
+        reader.read_exact(&mut format)?;
+
+        let format = match format[0] {
+            0 => HalfFormat::F16,
+            1 => HalfFormat::Bf16,
+            _ => return Err(invalid("wrong half format")),
+        };
+
+        let dim = read_u64(&mut reader)? as usize;
+        let dict = read_words(&mut reader)?;
+
+        let size = dict.len().checked_mul(dim)
+            .and_then(|size| size.checked_mul(2))
+            .ok_or_else(|| invalid("wrong model size"))?;
+        let buffer = read_bytes(&mut reader, size)?;
+
+        Ok(HalfEmbeddings {
+            dict,
+            dim,
+            format,
+            data: buffer.chunks(2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]])).collect(),
+        })
+    }
+
+    // converters from the word2vec f32 formats of `Embeddings`
+    pub fn convert_text<R, W>(reader: R, writer: &mut W, format: HalfFormat) -> io::Result<()>
+        where
+            R: BufRead,
+            W: Write
+    {
+        HalfEmbeddings::from_embeddings(&Embeddings::load_text(reader)?, format).save(writer)
+    }
+
+    pub fn convert_binary<R, W>(reader: R, writer: &mut W, format: HalfFormat) -> io::Result<()>
+        where
+            R: BufRead,
+            W: Write
+    {
+        HalfEmbeddings::from_embeddings(&Embeddings::load_binary(reader)?, format).save(writer)
+    }
+}
+
+impl WordVectorModel for HalfEmbeddings {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.dict.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.word_vector(word)
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        sentence_mean(text, self.dim, |word| self.word_vector(word))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use quantization::scalar::testing::random_embeddings;
+
+    #[test]
+    fn test_f16_conversion() {
+        assert_eq!(f32_to_f16(1.0), 0x3c00, "check one");
+        assert_eq!(f32_to_f16(-2.0), 0xc000, "check negative");
+        assert_eq!(f32_to_f16(65504.0), 0x7bff, "check max");
+        assert_eq!(f32_to_f16(70000.0), 0x7c00, "check overflow");
+        assert_eq!(f32_to_f16(1e-8), 0, "check underflow");
+        assert_eq!(f32_to_f16(5.960_464_5e-8), 1, "check min subnormal");
+        assert_eq!(f32_to_f16(1.0 + 1.0 / 2048.0), 0x3c00, "check tie to even");
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan(), "check nan");
+
+        for &v in [0.0f32, 1.0, -0.5, 3.140625, 65504.0, 6.103_515_6e-5, 5.960_464_5e-8].iter() {
+            assert_eq!(f16_to_f32(f32_to_f16(v)), v, "check exact {}", v);
+        }
+
+        assert_eq!(f32_to_bf16(1.0), 0x3f80, "check bf16 one");
+        assert_eq!(bf16_to_f32(f32_to_bf16(-3.0)), -3.0, "check bf16 exact");
+        assert_eq!(f32_to_bf16(1.0 + 1.0 / 256.0), 0x3f80, "check bf16 tie to even");
+        assert!(bf16_to_f32(f32_to_bf16(f32::NAN)).is_nan(), "check bf16 nan");
+    }
+
+    #[test]
+    fn test_half_embeddings() {
+        let original = random_embeddings(30, 8);
+
+        for &(format, tolerance) in [(HalfFormat::F16, 1e-3f32), (HalfFormat::Bf16, 1e-2)].iter() {
+            let model = HalfEmbeddings::from_embeddings(&original, format);
+
+            let exact = original.word_vector("w4").unwrap();
+            let approx = model.word_to_vector("w4").unwrap();
+
+            assert!(exact.iter().zip(approx.iter()).all(|(v1, v2)| (v1 - v2).abs() <= tolerance), "check {:?} vector", format);
+            assert_eq!(model.nearest(exact, 1)[0].0, 4, "check {:?} nearest", format);
+
+            let centroid = model.centroid(&["w1", "w2", "калитка"]).unwrap();
+            let expected: Vec<f32> = model.vector(1).unwrap().iter()
+                .zip(model.vector(2).unwrap().iter())
+                .map(|(v1, v2)| (v1 + v2) / 2.0)
+                .collect();
+            assert_eq!(centroid, expected, "check {:?} centroid", format);
+
+            assert!(model.accuracy(&original, 10).mean_cosine > 0.999, "check {:?} accuracy", format);
+        }
+    }
+
+    #[test]
+    fn test_half_embeddings_convert() {
+        let original = random_embeddings(5, 4);
+
+        let mut text: Vec<u8> = Vec::new();
+        original.save_text(&mut text).unwrap();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        HalfEmbeddings::convert_text(text.as_slice(), &mut buffer, HalfFormat::Bf16).unwrap();
+
+        assert_eq!(buffer.len(), 4 + 1 + 8 + 8 + 5 * (8 + 2) + 5 * 4 * 2, "check half size");
+
+        let exist = HalfEmbeddings::load(buffer.as_slice()).unwrap();
+        let expected = HalfEmbeddings::from_embeddings(&original, HalfFormat::Bf16);
+
+        assert_eq!(exist.format(), HalfFormat::Bf16, "check format");
+        assert_eq!(exist.raw(3), expected.raw(3), "check raw");
+        assert_eq!(exist.to_embeddings().unwrap().words(), original.words(), "check words");
+
+        let mut binary: Vec<u8> = Vec::new();
+        original.save_binary(&mut binary).unwrap();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        HalfEmbeddings::convert_binary(binary.as_slice(), &mut buffer, HalfFormat::F16).unwrap();
+        assert_eq!(HalfEmbeddings::load(buffer.as_slice()).unwrap().format(), HalfFormat::F16, "check binary convert");
+
+        assert!(HalfEmbeddings::load(&buffer[..buffer.len() - 1]).is_err(), "check truncated");
+    }
+}
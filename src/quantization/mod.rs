@@ -0,0 +1,176 @@
+use std::io::{self, Read, Write};
+
+use dictionary::Dictionary;
+use kernels::{dot, norm, squared_l2};
+use model::Embeddings;
+use utils::{invalid, read_bytes};
+
+pub mod scalar;
+pub mod product;
+pub mod half;
+
+// Accuracy loss of a compressed model against the original vectors on a sample
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccuracyReport {
+    pub samples: usize,
+    // per component reconstruction error
+    pub mean_squared_error: f32,
+    pub max_abs_error: f32,
+    // cosine similarity between an original and its reconstructed vector
+    pub mean_cosine: f32,
+    // relative error of euclidean distances between consecutive sampled words
+    pub distance_error: f32,
+}
+
+// `sample` words evenly spread over the vocabulary
+pub(crate) fn accuracy_report<F>(original: &Embeddings, sample: usize, decode: F) -> AccuracyReport
+    where F: Fn(usize) -> Vec<f32>
+{
+    let len = original.len();
+    let sample = sample.min(len);
+
+    let indices: Vec<usize> = (0..sample).map(|i| i * len / sample.max(1)).collect();
+
+    let mut squared_error = 0.0f64;
+    let mut max_abs_error = 0.0f32;
+    let mut cosine = 0.0f64;
+
+    let mut decoded: Vec<Vec<f32>> = Vec::with_capacity(sample);
+
+    for &index in indices.iter() {
+        let vector = original.vector(index).unwrap_or(&[]);
+        let approx = decode(index);
+
+        for (v1, v2) in vector.iter().zip(approx.iter()) {
+            squared_error += ((v1 - v2) * (v1 - v2)) as f64;
+            max_abs_error = max_abs_error.max((v1 - v2).abs());
+        }
+
+        let norm = norm(vector) * norm(&approx);
+        cosine += if norm > 0.0 { (dot(vector, &approx) / norm) as f64 } else { 1.0 };
+
+        decoded.push(approx);
+    }
+
+    let mut distance_error = 0.0f64;
+    let mut pairs = 0;
+
+    for i in 1..indices.len() {
+        let exact = euclidean(original.vector(indices[i - 1]).unwrap_or(&[]), original.vector(indices[i]).unwrap_or(&[]));
+
+        if exact > 0.0 {
+            distance_error += ((euclidean(&decoded[i - 1], &decoded[i]) - exact).abs() / exact) as f64;
+            pairs += 1;
+        }
+    }
+
+    let components = (sample * original.dim()).max(1);
+
+    AccuracyReport {
+        samples: sample,
+        mean_squared_error: (squared_error / components as f64) as f32,
+        max_abs_error,
+        mean_cosine: (cosine / sample.max(1) as f64) as f32,
+        distance_error: (distance_error / pairs.max(1) as f64) as f32,
+    }
+}
+
+fn euclidean(vec1: &[f32], vec2: &[f32]) -> f32 {
+    squared_l2(vec1, vec2).sqrt()
+}
+
+// the words in the same index order, without their counts
+pub(crate) fn dictionary_copy(dict: &Dictionary) -> Dictionary {
+    let mut copy = Dictionary::default();
+
+    for (_, word) in dict.iter_indexed() {
+        copy.insert(word);
+    }
+
+    copy
+}
+
+pub(crate) fn check_magic<R>(reader: &mut R, magic: &[u8; 4]) -> io::Result<()>
+    where R: Read
+{
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+
+    if &buffer != magic {
+        return Err(invalid("wrong model format"));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn write_u64<W>(writer: &mut W, value: u64) -> io::Result<()>
+    where W: Write
+{
+    writer.write_all(&value.to_le_bytes())
+}
+
+pub(crate) fn read_u64<R>(reader: &mut R) -> io::Result<u64>
+    where R: Read
+{
+    let mut buffer = [0u8; 8];
+    reader.read_exact(&mut buffer)?;
+
+    Ok(u64::from_le_bytes(buffer))
+}
+
+pub(crate) fn write_f32s<W>(writer: &mut W, values: &[f32]) -> io::Result<()>
+    where W: Write
+{
+    for v in values {
+        writer.write_all(&v.to_bits().to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_f32s<R>(reader: &mut R, len: usize) -> io::Result<Vec<f32>>
+    where R: Read
+{
+    let size = len.checked_mul(4).ok_or_else(|| invalid("wrong model size"))?;
+    let buffer = read_bytes(reader, size)?;
+
+    Ok(buffer.chunks(4)
+        .map(|bytes| f32::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+        .collect())
+}
+
+// words in index order, each is a length followed by utf-8 bytes
+pub(crate) fn write_words<W>(writer: &mut W, dict: &Dictionary) -> io::Result<()>
+    where W: Write
+{
+    write_u64(writer, dict.len() as u64)?;
+
+    for (_, word) in dict.iter_indexed() {
+        write_u64(writer, word.len() as u64)?;
+        writer.write_all(word.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn read_words<R>(reader: &mut R) -> io::Result<Dictionary>
+    where R: Read
+{
+    let len = read_u64(reader)?;
+    let mut dict = Dictionary::default();
+
+    for _ in 0..len {
+        let len = read_u64(reader)? as usize;
+        let word = read_bytes(reader, len)?;
+
+        let word = String::from_utf8(word).map_err(|_| invalid("wrong word encoding"))?;
+
+        if dict.contains(&word) {
+            return Err(invalid("duplicate word"));
+        }
+
+        dict.insert(word);
+    }
+
+    Ok(dict)
+}
@@ -0,0 +1,379 @@
+use std::io::{self, Read, Write};
+
+use dictionary::Dictionary;
+use kernels::{axpy, squared_l2};
+use model::Embeddings;
+use quantization::{AccuracyReport, accuracy_report, dictionary_copy};
+use quantization::{check_magic, write_u64, read_u64, write_f32s, read_f32s, write_words, read_words};
+use train::Rng;
+use utils::{invalid, read_bytes, sentence_mean, smallest_k};
+use WordVectorModel;
+
+const MAGIC: &[u8; 4] = b"WVPQ";
+
+// Product quantization: a vector is split into `subspaces` equal parts,
+// every part is replaced by the nearest of `centroids` k-means centroids of its subspace
+#[derive(Debug, Clone)]
+pub struct ProductQuantization {
+    pub subspaces: usize,
+    pub centroids: usize,
+    pub iterations: usize,
+    // vectors used to fit the codebooks, 0 uses all of them
+    pub sample: usize,
+    pub seed: u64,
+}
+
+impl Default for ProductQuantization {
+    fn default() -> ProductQuantization {
+        ProductQuantization {
+            subspaces: 50,
+            centroids: 256,
+            iterations: 25,
+            sample: 65_536,
+            seed: 1,
+        }
+    }
+}
+
+fn nearest_centroid(centroids: &[f32], dsub: usize, vector: &[f32]) -> usize {
+    centroids.chunks(dsub)
+        .map(|centroid| squared_l2(centroid, vector))
+        .enumerate()
+        .fold((0, f32::INFINITY), |(best, best_distance), (index, distance)| {
+            if distance < best_distance { (index, distance) } else { (best, best_distance) }
+        })
+        .0
+}
+
+impl ProductQuantization {
+    pub fn quantize(&self, embeddings: &Embeddings) -> Result<ProductQuantized, &'static str> {
+        let (dim, len) = (embeddings.dim(), embeddings.len());
+
+        if self.subspaces == 0 || !dim.is_multiple_of(self.subspaces) {
+            return Err("dimension is not divisible by subspaces");
+        }
+
+        if self.centroids == 0 || self.centroids > 256 {
+            return Err("wrong centroids count");
+        }
+
+        if len == 0 {
+            return Err("empty embeddings");
+        }
+
+        let dsub = dim / self.subspaces;
+        let centroids = self.centroids.min(len);
+
+        let mut rng = Rng::new(self.seed);
+
+        let mut order: Vec<usize> = (0..len).collect();
+        for i in (1..len).rev() {
+            order.swap(i, rng.below(i + 1));
+        }
+
+        let sample = if self.sample == 0 { len } else { self.sample.min(len).max(centroids) };
+        let sample = &order[..sample];
+
+        let mut codebooks: Vec<f32> = Vec::with_capacity(self.subspaces * centroids * dsub);
+
+        for m in 0..self.subspaces {
+            let part = |index: usize| &embeddings.vector(index).unwrap_or(&[])[m * dsub..(m + 1) * dsub];
+
+            codebooks.extend(self.kmeans(&sample.iter().map(|&index| part(index)).collect::<Vec<&[f32]>>(),
+                                         centroids, dsub, &mut rng));
+        }
+
+        let codebook_size = centroids * dsub;
+
+        let codes: Vec<u8> = (0..len)
+            .flat_map(|index| {
+                let vector = embeddings.vector(index).unwrap_or(&[]);
+                let codebooks = &codebooks;
+
+                (0..self.subspaces).map(move |m| {
+                    nearest_centroid(&codebooks[m * codebook_size..(m + 1) * codebook_size], dsub,
+                                     &vector[m * dsub..(m + 1) * dsub]) as u8
+                })
+            })
+            .collect();
+
+        Ok(ProductQuantized {
+            dict: dictionary_copy(embeddings.dictionary()),
+            dim,
+            subspaces: self.subspaces,
+            centroids,
+            codebooks,
+            codes,
+        })
+    }
+
+    // Lloyd iterations from randomly picked points, an empty cluster takes a random point
+    fn kmeans(&self, points: &[&[f32]], k: usize, dsub: usize, rng: &mut Rng) -> Vec<f32> {
+        let mut centroids: Vec<f32> = points.iter()
+            .take(k)
+            .flat_map(|point| point.iter().cloned())
+            .collect();
+
+        let mut assignment: Vec<usize> = vec![0; points.len()];
+
+        for _ in 0..self.iterations {
+            for (point, assigned) in points.iter().zip(assignment.iter_mut()) {
+                *assigned = nearest_centroid(&centroids, dsub, point);
+            }
+
+            let mut counts: Vec<usize> = vec![0; k];
+            centroids.iter_mut().for_each(|v| *v = 0.0);
+
+            for (point, &assigned) in points.iter().zip(assignment.iter()) {
+                counts[assigned] += 1;
+
+                axpy(1.0, point, &mut centroids[assigned * dsub..(assigned + 1) * dsub]);
+            }
+
+            for (c, &count) in counts.iter().enumerate() {
+                let centroid = &mut centroids[c * dsub..(c + 1) * dsub];
+
+                if count == 0 {
+                    centroid.copy_from_slice(points[rng.below(points.len())]);
+                } else {
+                    centroid.iter_mut().for_each(|v| *v /= count as f32);
+                }
+            }
+        }
+
+        centroids
+    }
+}
+
+// Words with one byte code per subspace and the codebooks to decode them
+#[derive(Debug)]
+pub struct ProductQuantized {
+    dict: Dictionary,
+    dim: usize,
+    subspaces: usize,
+    centroids: usize,
+    // subspaces x centroids x dim / subspaces
+    codebooks: Vec<f32>,
+    codes: Vec<u8>,
+}
+
+impl ProductQuantized {
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn subspaces(&self) -> usize {
+        self.subspaces
+    }
+
+    pub fn centroids(&self) -> usize {
+        self.centroids
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    pub fn codes(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(&self.codes[index * self.subspaces..(index + 1) * self.subspaces])
+    }
+
+    fn centroid(&self, m: usize, code: u8) -> &[f32] {
+        let dsub = self.dim / self.subspaces;
+        let start = (m * self.centroids + code as usize) * dsub;
+
+        &self.codebooks[start..start + dsub]
+    }
+
+    pub fn vector(&self, index: usize) -> Option<Vec<f32>> {
+        let codes = self.codes(index)?;
+
+        Some(codes.iter()
+            .enumerate()
+            .flat_map(|(m, &code)| self.centroid(m, code).iter().cloned())
+            .collect())
+    }
+
+    pub fn word_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.vector(self.dict.word_index(word)? as usize)
+    }
+
+    // squared distances from the query parts to every centroid, subspaces x centroids
+    pub fn distance_table(&self, query: &[f32]) -> Option<Vec<f32>> {
+        if query.len() != self.dim {
+            return None;
+        }
+
+        let dsub = self.dim / self.subspaces;
+
+        Some((0..self.subspaces)
+            .flat_map(|m| {
+                let part = &query[m * dsub..(m + 1) * dsub];
+
+                (0..self.centroids).map(move |c| squared_l2(part, self.centroid(m, c as u8)))
+            })
+            .collect())
+    }
+
+    // asymmetric distance, a table lookup per subspace
+    pub fn squared_euclidean_table(&self, table: &[f32], index: usize) -> Option<f32> {
+        let codes = self.codes(index)?;
+
+        Some(codes.iter()
+            .enumerate()
+            .map(|(m, &code)| table[m * self.centroids + code as usize])
+            .sum())
+    }
+
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let table = match self.distance_table(query) {
+            Some(table) => table,
+            None => return Vec::new(),
+        };
+
+        let distances: Vec<(usize, f32)> = (0..self.len())
+            .filter_map(|index| self.squared_euclidean_table(&table, index).map(|distance| (index, distance)))
+            .collect();
+
+        smallest_k(distances, k)
+    }
+
+    pub fn accuracy(&self, original: &Embeddings, sample: usize) -> AccuracyReport {
+        accuracy_report(original, sample, |index| self.vector(index).unwrap_or_default())
+    }
+
+    // magic, dim, subspaces, centroids, words, codebooks, codes; little-endian
+    pub fn save<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writer.write_all(MAGIC)?;
+        write_u64(writer, self.dim as u64)?;
+        write_u64(writer, self.subspaces as u64)?;
+        write_u64(writer, self.centroids as u64)?;
+        write_words(writer, &self.dict)?;
+        write_f32s(writer, &self.codebooks)?;
+        writer.write_all(&self.codes)
+    }
+
+    pub fn load<R>(mut reader: R) -> io::Result<ProductQuantized>
+        where R: Read
+    {
+        check_magic(&mut reader, MAGIC)?;
+
+        let dim = read_u64(&mut reader)? as usize;
+        let subspaces = read_u64(&mut reader)? as usize;
+        let centroids = read_u64(&mut reader)? as usize;
+
+        if subspaces == 0 || !dim.is_multiple_of(subspaces) || centroids == 0 || centroids > 256 {
+            return Err(invalid("wrong quantizer parameters"));
+        }
+
+        let dict = read_words(&mut reader)?;
+        let codebooks = read_f32s(&mut reader, centroids.checked_mul(dim).ok_or_else(|| invalid("wrong model size"))?)?;
+
+        let size = dict.len().checked_mul(subspaces).ok_or_else(|| invalid("wrong model size"))?;
+        let codes = read_bytes(&mut reader, size)?;
+
+        if codes.iter().any(|&code| code as usize >= centroids) {
+            return Err(invalid("code out of codebook"));
+        }
+
+        Ok(ProductQuantized {
+            dict,
+            dim,
+            subspaces,
+            centroids,
+            codebooks,
+            codes,
+        })
+    }
+}
+
+impl WordVectorModel for ProductQuantized {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.dict.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.word_vector(word)
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        sentence_mean(text, self.dim, |word| self.word_vector(word))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use quantization::scalar::testing::random_embeddings;
+
+    fn quantizer() -> ProductQuantization {
+        ProductQuantization {
+            subspaces: 4,
+            centroids: 16,
+            iterations: 10,
+            ..ProductQuantization::default()
+        }
+    }
+
+    #[test]
+    fn test_product_quantized() {
+        let original = random_embeddings(200, 16);
+
+        let model = quantizer().quantize(&original).unwrap();
+
+        assert_eq!(model.len(), 200, "check length");
+        assert_eq!(model.codes(3).unwrap().len(), 4, "check code size");
+        assert_eq!(model.word_index("w3"), Some(3), "check index");
+        assert_eq!(model.word_to_vector("w3"), model.vector(3), "check vector");
+
+        let query = original.vector(42).unwrap();
+        let table = model.distance_table(query).unwrap();
+        let approx = model.vector(42).unwrap();
+
+        let exact: f32 = query.iter().zip(approx.iter()).map(|(v1, v2)| (v1 - v2) * (v1 - v2)).sum();
+        assert!((model.squared_euclidean_table(&table, 42).unwrap() - exact).abs() < 1e-4, "check adc");
+
+        let nearest: Vec<usize> = model.nearest(query, 5).iter().map(|&(index, _)| index).collect();
+        assert!(nearest.contains(&42), "check nearest {:?}", nearest);
+
+        let report = model.accuracy(&original, 50);
+        assert!(report.mean_cosine > 0.6, "check cosine {}", report.mean_cosine);
+
+        // more centroids fit better
+        let finer = ProductQuantization { centroids: 64, ..quantizer() }.quantize(&original).unwrap();
+        assert!(finer.accuracy(&original, 50).mean_squared_error < report.mean_squared_error, "check finer codebooks");
+
+        assert!(ProductQuantization { subspaces: 3, ..quantizer() }.quantize(&original).is_err(), "check subspaces");
+    }
+
+    #[test]
+    fn test_product_quantized_save_load() {
+        let original = random_embeddings(40, 8);
+        let model = quantizer().quantize(&original).unwrap();
+
+        let mut buffer: Vec<u8> = Vec::new();
+        model.save(&mut buffer).unwrap();
+
+        let exist = ProductQuantized::load(buffer.as_slice()).unwrap();
+
+        assert_eq!(exist.centroids(), 16, "check centroids");
+        assert_eq!(exist.codes(7), model.codes(7), "check codes");
+        assert_eq!(exist.word_vector("w7"), model.word_vector("w7"), "check vector");
+
+        assert!(ProductQuantized::load(&buffer[..buffer.len() - 1]).is_err(), "check truncated");
+    }
+}
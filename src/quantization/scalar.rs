@@ -0,0 +1,400 @@
+use std::io::{self, Read, Write};
+
+use dictionary::Dictionary;
+use model::Embeddings;
+use quantization::{AccuracyReport, accuracy_report, dictionary_copy};
+use quantization::{check_magic, write_u64, read_u64, write_f32s, read_f32s, write_words, read_words};
+use utils::{invalid, read_bytes, sentence_mean, smallest_k};
+use WordVectorModel;
+
+const MAGIC: &[u8; 4] = b"WVQ8";
+const LEVELS: f32 = 255.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    // a scale and an offset per dimension shared by all vectors
+    PerDimension,
+    // a scale and an offset per vector
+    PerVector,
+}
+
+// 8-bit scalar quantized vectors, a component is offset + scale * code
+#[derive(Debug)]
+pub struct ScalarQuantized {
+    dict: Dictionary,
+    dim: usize,
+    mode: ScaleMode,
+    scales: Vec<f32>,
+    offsets: Vec<f32>,
+    codes: Vec<u8>,
+    // per dimension mode: scale^2, offset * scale and sum of offset^2
+    weights: Vec<f32>,
+    cross: Vec<f32>,
+    bias: f32,
+}
+
+fn range<I>(values: I) -> (f32, f32)
+    where I: Iterator<Item=f32>
+{
+    let (min, max) = values.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    });
+
+    if min > max {
+        return (0.0, 0.0);
+    }
+
+    (min, (max - min) / LEVELS)
+}
+
+fn encode(value: f32, offset: f32, scale: f32) -> u8 {
+    if scale <= 0.0 {
+        return 0;
+    }
+
+    ((value - offset) / scale).round().clamp(0.0, LEVELS) as u8
+}
+
+impl ScalarQuantized {
+    pub fn quantize(embeddings: &Embeddings, mode: ScaleMode) -> ScalarQuantized {
+        let (dim, len) = (embeddings.dim(), embeddings.len());
+        let vectors = embeddings.as_slice();
+
+        let params: Vec<(f32, f32)> = match mode {
+            ScaleMode::PerDimension => (0..dim)
+                .map(|d| range((0..len).map(|i| vectors[i * dim + d])))
+                .collect(),
+            ScaleMode::PerVector => (0..len)
+                .map(|i| range(vectors[i * dim..(i + 1) * dim].iter().cloned()))
+                .collect(),
+        };
+
+        let codes: Vec<u8> = vectors.iter()
+            .enumerate()
+            .map(|(pos, &value)| {
+                let (offset, scale) = match mode {
+                    ScaleMode::PerDimension => params[pos % dim],
+                    ScaleMode::PerVector => params[pos / dim],
+                };
+
+                encode(value, offset, scale)
+            })
+            .collect();
+
+        let (offsets, scales) = params.into_iter().unzip();
+
+        ScalarQuantized::with_codes(dictionary_copy(embeddings.dictionary()), dim, mode, scales, offsets, codes)
+    }
+
+    fn with_codes(dict: Dictionary, dim: usize, mode: ScaleMode, scales: Vec<f32>, offsets: Vec<f32>, codes: Vec<u8>) -> ScalarQuantized {
+        let (weights, cross, bias) = match mode {
+            ScaleMode::PerDimension => (
+                scales.iter().map(|s| s * s).collect(),
+                scales.iter().zip(offsets.iter()).map(|(s, o)| s * o).collect(),
+                offsets.iter().map(|o| o * o).sum(),
+            ),
+            ScaleMode::PerVector => (Vec::new(), Vec::new(), 0.0),
+        };
+
+        ScalarQuantized {
+            dict,
+            dim,
+            mode,
+            scales,
+            offsets,
+            codes,
+            weights,
+            cross,
+            bias,
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn mode(&self) -> ScaleMode {
+        self.mode
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    pub fn codes(&self, index: usize) -> Option<&[u8]> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(&self.codes[index * self.dim..(index + 1) * self.dim])
+    }
+
+    pub fn vector(&self, index: usize) -> Option<Vec<f32>> {
+        let codes = self.codes(index)?;
+
+        Some(match self.mode {
+            ScaleMode::PerDimension => codes.iter()
+                .zip(self.scales.iter().zip(self.offsets.iter()))
+                .map(|(&c, (s, o))| o + s * c as f32)
+                .collect(),
+            ScaleMode::PerVector => {
+                let (s, o) = (self.scales[index], self.offsets[index]);
+
+                codes.iter().map(|&c| o + s * c as f32).collect()
+            }
+        })
+    }
+
+    pub fn word_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.vector(self.dict.word_index(word)? as usize)
+    }
+
+    // computed on the codes without dequantization
+    pub fn dot(&self, i: usize, j: usize) -> Option<f32> {
+        let (codes1, codes2) = (self.codes(i)?, self.codes(j)?);
+
+        Some(match self.mode {
+            ScaleMode::PerDimension => {
+                let value: f32 = codes1.iter()
+                    .zip(codes2.iter())
+                    .enumerate()
+                    .map(|(d, (&c1, &c2))| {
+                        self.cross[d] * (c1 as f32 + c2 as f32) + self.weights[d] * (c1 as u32 * c2 as u32) as f32
+                    })
+                    .sum();
+
+                self.bias + value
+            }
+            ScaleMode::PerVector => {
+                let (mut sum1, mut sum2, mut product) = (0u64, 0u64, 0u64);
+
+                for (&c1, &c2) in codes1.iter().zip(codes2.iter()) {
+                    sum1 += c1 as u64;
+                    sum2 += c2 as u64;
+                    product += c1 as u64 * c2 as u64;
+                }
+
+                let (s1, o1) = (self.scales[i] as f64, self.offsets[i] as f64);
+                let (s2, o2) = (self.scales[j] as f64, self.offsets[j] as f64);
+
+                (self.dim as f64 * o1 * o2 + o1 * s2 * sum2 as f64 + o2 * s1 * sum1 as f64
+                    + s1 * s2 * product as f64) as f32
+            }
+        })
+    }
+
+    pub fn squared_euclidean(&self, i: usize, j: usize) -> Option<f32> {
+        match self.mode {
+            ScaleMode::PerDimension => {
+                let (codes1, codes2) = (self.codes(i)?, self.codes(j)?);
+
+                Some(codes1.iter()
+                    .zip(codes2.iter())
+                    .zip(self.weights.iter())
+                    .map(|((&c1, &c2), w)| {
+                        let diff = c1 as i32 - c2 as i32;
+
+                        w * (diff * diff) as f32
+                    })
+                    .sum())
+            }
+            ScaleMode::PerVector => {
+                let value = self.dot(i, i)? + self.dot(j, j)? - 2.0 * self.dot(i, j)?;
+
+                Some(value.max(0.0))
+            }
+        }
+    }
+
+    pub fn cosine(&self, i: usize, j: usize) -> Option<f32> {
+        let norm = (self.dot(i, i)? * self.dot(j, j)?).sqrt();
+
+        if norm <= 0.0 {
+            return Some(0.0);
+        }
+
+        Some(self.dot(i, j)? / norm)
+    }
+
+    // asymmetric kernels, the query stays in f32
+    pub fn dot_query(&self, query: &[f32], index: usize) -> Option<f32> {
+        let codes = self.codes(index)?;
+
+        if query.len() != self.dim {
+            return None;
+        }
+
+        Some(match self.mode {
+            ScaleMode::PerDimension => query.iter()
+                .zip(codes.iter())
+                .zip(self.scales.iter().zip(self.offsets.iter()))
+                .map(|((q, &c), (s, o))| q * (o + s * c as f32))
+                .sum(),
+            ScaleMode::PerVector => {
+                let (sum, product) = query.iter()
+                    .zip(codes.iter())
+                    .fold((0.0f32, 0.0f32), |(sum, product), (q, &c)| (sum + q, product + q * c as f32));
+
+                self.offsets[index] * sum + self.scales[index] * product
+            }
+        })
+    }
+
+    pub fn squared_euclidean_query(&self, query: &[f32], index: usize) -> Option<f32> {
+        let norm: f32 = query.iter().map(|q| q * q).sum();
+        let value = norm + self.dot(index, index)? - 2.0 * self.dot_query(query, index)?;
+
+        Some(value.max(0.0))
+    }
+
+    // brute force by the squared euclidean distance
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let distances: Vec<(usize, f32)> = (0..self.len())
+            .filter_map(|index| self.squared_euclidean_query(query, index).map(|distance| (index, distance)))
+            .collect();
+
+        smallest_k(distances, k)
+    }
+
+    pub fn accuracy(&self, original: &Embeddings, sample: usize) -> AccuracyReport {
+        accuracy_report(original, sample, |index| self.vector(index).unwrap_or_default())
+    }
+
+    // magic, mode, dim, words, scales, offsets, codes; little-endian
+    pub fn save<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[match self.mode {
+            ScaleMode::PerDimension => 0,
+            ScaleMode::PerVector => 1,
+        }])?;
+        write_u64(writer, self.dim as u64)?;
+        write_words(writer, &self.dict)?;
+        write_f32s(writer, &self.scales)?;
+        write_f32s(writer, &self.offsets)?;
+        writer.write_all(&self.codes)
+    }
+
+    pub fn load<R>(mut reader: R) -> io::Result<ScalarQuantized>
+        where R: Read
+    {
+        check_magic(&mut reader, MAGIC)?;
+
+        let mut mode = [0u8; 1];
+        reader.read_exact(&mut mode)?;
+
+        let mode = match mode[0] {
+            0 => ScaleMode::PerDimension,
+            1 => ScaleMode::PerVector,
+            _ => return Err(invalid("wrong scale mode")),
+        };
+
+        let dim = read_u64(&mut reader)? as usize;
+        let dict = read_words(&mut reader)?;
+
+        let params = match mode {
+            ScaleMode::PerDimension => dim,
+            ScaleMode::PerVector => dict.len(),
+        };
+
+        let scales = read_f32s(&mut reader, params)?;
+        let offsets = read_f32s(&mut reader, params)?;
+
+        let size = dict.len().checked_mul(dim).ok_or_else(|| invalid("wrong model size"))?;
+        let codes = read_bytes(&mut reader, size)?;
+
+        Ok(ScalarQuantized::with_codes(dict, dim, mode, scales, offsets, codes))
+    }
+}
+
+impl WordVectorModel for ScalarQuantized {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.dict.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.word_vector(word)
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        sentence_mean(text, self.dim, |word| self.word_vector(word))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use train::Rng;
+
+    pub(crate) fn random_embeddings(len: usize, dim: usize) -> Embeddings {
+        let mut rng = Rng::new(3);
+
+        let words: Vec<String> = (0..len).map(|i| format!("w{}", i)).collect();
+        let vectors: Vec<f32> = (0..len * dim).map(|_| rng.next_f32() * 2.0 - 1.0).collect();
+
+        Embeddings::with_vectors(&words, dim, vectors).unwrap()
+    }
+
+    fn dot(vec1: &[f32], vec2: &[f32]) -> f32 {
+        vec1.iter().zip(vec2.iter()).map(|(v1, v2)| v1 * v2).sum()
+    }
+
+    #[test]
+    fn test_scalar_quantized() {
+        let original = random_embeddings(50, 16);
+
+        for &mode in [ScaleMode::PerDimension, ScaleMode::PerVector].iter() {
+            let model = ScalarQuantized::quantize(&original, mode);
+
+            assert_eq!(model.len(), 50, "check length");
+            assert_eq!(model.word_index("w7"), Some(7), "check index");
+
+            let exact = original.word_vector("w7").unwrap();
+            let approx = model.word_to_vector("w7").unwrap();
+
+            assert!(exact.iter().zip(approx.iter()).all(|(v1, v2)| (v1 - v2).abs() < 2.0 / 255.0),
+                    "check {:?} reconstruction", mode);
+
+            // code kernels agree with the dequantized vectors
+            let (v3, v9) = (model.vector(3).unwrap(), model.vector(9).unwrap());
+            let squared: f32 = v3.iter().zip(v9.iter()).map(|(v1, v2)| (v1 - v2) * (v1 - v2)).sum();
+
+            assert!((model.dot(3, 9).unwrap() - dot(&v3, &v9)).abs() < 1e-4, "check {:?} dot", mode);
+            assert!((model.squared_euclidean(3, 9).unwrap() - squared).abs() < 1e-3, "check {:?} distance", mode);
+            assert!((model.dot_query(exact, 9).unwrap() - dot(exact, &v9)).abs() < 1e-4, "check {:?} query", mode);
+
+            assert_eq!(model.nearest(exact, 1)[0].0, 7, "check {:?} nearest", mode);
+
+            let report = model.accuracy(&original, 20);
+            assert_eq!(report.samples, 20, "check samples");
+            assert!(report.mean_cosine > 0.999, "check {:?} cosine {}", mode, report.mean_cosine);
+            assert!(report.distance_error < 0.01, "check {:?} distance error {}", mode, report.distance_error);
+        }
+    }
+
+    #[test]
+    fn test_scalar_quantized_save_load() {
+        let original = random_embeddings(10, 4);
+        let model = ScalarQuantized::quantize(&original, ScaleMode::PerVector);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        model.save(&mut buffer).unwrap();
+
+        let exist = ScalarQuantized::load(buffer.as_slice()).unwrap();
+
+        assert_eq!(exist.mode(), ScaleMode::PerVector, "check mode");
+        assert_eq!(exist.codes(5), model.codes(5), "check codes");
+        assert_eq!(exist.word_vector("w5"), model.word_vector("w5"), "check vector");
+
+        assert!(ScalarQuantized::load(&buffer[..buffer.len() - 1]).is_err(), "check truncated");
+    }
+}
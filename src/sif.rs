@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use kernels;
+use utils::invalid;
+use WordVectorModel;
+
+pub const SIF_DEFAULT_A: f32 = 1e-3;
+
+const PC_ITERATIONS: usize = 100;
+const PC_EPSILON: f32 = 1e-6;
+
+enum WordProbability {
+    Frequency(BTreeMap<String, f32>),
+    // word2vec-like models store vocabulary sorted by frequency, so the index is a rank
+    // and the probability is estimated with Zipf's law
+    Rank { vocab_size: usize, harmonic: f32 },
+}
+
+pub struct Sif {
+    a: f32,
+    probability: WordProbability,
+    component: Option<Vec<f32>>,
+}
+
+impl Sif {
+    pub fn with_frequency<I, T>(a: f32, counts: I) -> Sif
+        where
+            I: IntoIterator<Item=(T, u64)>,
+            T: ToString
+    {
+        let counts: Vec<(String, u64)> = counts.into_iter()
+            .map(|(word, count)| (word.to_string(), count))
+            .collect();
+
+        let total = counts.iter().fold(0u64, |acc, &(_, count)| acc + count) as f32;
+
+        let probability = counts.into_iter()
+            .filter(|&(_, count)| count > 0)
+            .map(|(word, count)| (word, count as f32 / total))
+            .collect();
+
+        Sif {
+            a,
+            probability: WordProbability::Frequency(probability),
+            component: None,
+        }
+    }
+
+    pub fn with_vocabulary_order(a: f32, vocab_size: usize) -> Sif {
+        let harmonic = (1..vocab_size + 1)
+            .fold(0.0f64, |acc, rank| acc + 1.0 / rank as f64) as f32;
+
+        Sif {
+            a,
+            probability: WordProbability::Rank { vocab_size, harmonic },
+            component: None,
+        }
+    }
+
+    // a frequency file has a "word count" pair per line
+    pub fn from_frequency_file<P>(a: f32, path: P) -> io::Result<Sif>
+        where P: AsRef<Path>
+    {
+        let reader = BufReader::new(File::open(path)?);
+        let mut counts: Vec<(String, u64)> = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut fields = line.split_whitespace();
+
+            let word = match fields.next() {
+                Some(word) => word,
+                None => continue,
+            };
+
+            let count = match fields.next().map(|count| count.parse::<u64>()) {
+                Some(Ok(count)) => count,
+                _ => return Err(invalid("wrong word count")),
+            };
+
+            counts.push((word.to_string(), count));
+        }
+
+        Ok(Sif::with_frequency(a, counts))
+    }
+
+    pub fn component(&self) -> Option<&[f32]> {
+        match self.component {
+            Some(ref component) => Some(component.as_slice()),
+            None => None,
+        }
+    }
+
+    pub fn probability(&self, model: &dyn WordVectorModel, word: &str) -> f32 {
+        match self.probability {
+            WordProbability::Frequency(ref probability) => match probability.get(word) {
+                Some(&p) => p,
+                None => 0.0,
+            },
+            WordProbability::Rank { vocab_size, harmonic } => match model.word_index(word) {
+                Some(index) if index >= 0 && (index as usize) < vocab_size =>
+                    1.0 / ((index + 1) as f32 * harmonic),
+                _ => 0.0,
+            },
+        }
+    }
+
+    pub fn weight(&self, model: &dyn WordVectorModel, word: &str) -> f32 {
+        self.a / (self.a + self.probability(model, word))
+    }
+
+    pub(crate) fn weighted_average(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        let mut average: Vec<f32> = Vec::new();
+        let mut count = 0usize;
+
+        for word in doc {
+            let vector = match model.word_to_vector(word) {
+                Some(vector) => vector,
+                None => continue,
+            };
+
+            if average.is_empty() {
+                average.resize(vector.len(), 0.0);
+            }
+
+            let weight = self.weight(model, word);
+
+            kernels::axpy(weight, &vector, &mut average);
+
+            count += 1;
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        average.iter_mut()
+            .for_each(|v| *v /= count as f32);
+
+        Some(average)
+    }
+
+    pub fn fit<T>(&mut self, model: &dyn WordVectorModel, corpus: &[Vec<T>]) -> Result<(), &str>
+        where T: ToString
+    {
+        let averages: Vec<Vec<f32>> = corpus.iter()
+            .filter_map(|doc| {
+                let doc: Vec<String> = doc.iter().map(|word| word.to_string()).collect();
+
+                self.weighted_average(model, &doc)
+            })
+            .collect();
+
+        if averages.is_empty() {
+            return Err("empty reference corpus");
+        }
+
+        self.component = first_principal_component(&averages);
+
+        Ok(())
+    }
+
+    pub fn embed(&self, model: &dyn WordVectorModel, doc: &[String]) -> Option<Vec<f32>> {
+        let mut embedding = self.weighted_average(model, doc)?;
+
+        if let Some(ref component) = self.component {
+            let projection = kernels::dot(&embedding, component);
+
+            kernels::axpy(-projection, component, &mut embedding);
+        }
+
+        Some(embedding)
+    }
+}
+
+// power iteration over X^T X, rows of X are the sentence embeddings
+fn first_principal_component(rows: &[Vec<f32>]) -> Option<Vec<f32>> {
+    let dim = rows[0].len();
+
+    let mut component: Vec<f32> = vec![1.0 / (dim as f32).sqrt(); dim];
+
+    for _ in 0..PC_ITERATIONS {
+        let mut next: Vec<f32> = vec![0.0; dim];
+
+        for row in rows {
+            let projection = kernels::dot(row, &component);
+
+            kernels::axpy(projection, row, &mut next);
+        }
+
+        let norm = kernels::norm(&next);
+        if norm == 0.0 {
+            return None;
+        }
+
+        next.iter_mut()
+            .for_each(|v| *v /= norm);
+
+        let delta = kernels::l1(&next, &component);
+
+        component = next;
+
+        if delta < PC_EPSILON {
+            break;
+        }
+    }
+
+    Some(component)
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use testing::model::TestModel;
+
+    #[test]
+    fn test_sif_frequency_weight() {
+        let model = TestModel::default();
+        let sif = Sif::with_frequency(0.5, vec![("намело", 3u64), ("сугробы", 1)]);
+
+        assert_eq!(sif.probability(&model, "намело"), 0.75, "check probability");
+        assert_eq!(sif.weight(&model, "сугробы"), 0.5/(0.5 + 0.25), "check weight");
+        assert_eq!(sif.weight(&model, "крыльца"), 1.0, "check unknown word weight");
+    }
+
+    #[test]
+    fn test_sif_vocabulary_order_weight() {
+        let model = TestModel::default();
+        let sif = Sif::with_vocabulary_order(SIF_DEFAULT_A, 5);
+
+        assert!(sif.weight(&model, "намело") < sif.weight(&model, "крыльца"), "check frequent word weight");
+        assert_eq!(sif.weight(&model, "калитка"), 1.0, "check unknown word weight");
+    }
+
+    #[test]
+    fn test_sif_principal_component() {
+        let rows = vec![
+            vec![2.0f32, 0.0],
+            vec![-3.0f32, 0.1],
+            vec![1.0f32, -0.1],
+        ];
+
+        let component = first_principal_component(&rows).unwrap();
+
+        assert!(component[0].abs() > 0.99, "check component direction");
+    }
+
+    #[test]
+    fn test_sif_fit_embed() {
+        let model = TestModel::default();
+        let mut sif = Sif::with_vocabulary_order(SIF_DEFAULT_A, 5);
+
+        let corpus = vec![
+            vec!["намело", "сугробы"],
+            vec!["у", "нашего", "крыльца"],
+            vec!["сугробы", "у", "крыльца"],
+        ];
+
+        assert!(sif.fit(&model, &corpus).is_ok(), "check fit");
+
+        let component = sif.component().unwrap().to_vec();
+
+        let doc = vec!["намело".to_string(), "у".to_string()];
+        let embedding = sif.embed(&model, &doc).unwrap();
+
+        let projection: f32 = embedding.iter()
+            .zip(component.iter())
+            .map(|(v, u)| v * u)
+            .sum();
+
+        assert!(projection.abs() < 1e-5, "check common component removed");
+
+        let empty: Vec<Vec<&str>> = vec![vec!["калитка"]];
+        assert!(sif.fit(&model, &empty).is_err(), "check empty corpus");
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::{self, BufRead, Write};
+
+use bow::{SparseBow, sparse_dot};
+use coverage::Coverage;
+use dictionary::Dictionary;
+use kernels;
+use utils::invalid;
+use WordVector;
+
+pub const TERM_SIMILARITY_THRESHOLD: f32 = 0.0;
+pub const TERM_SIMILARITY_EXPONENT: f32 = 2.0;
+
+// Sparse symmetric term similarity matrix, the diagonal is implicitly 1.0
+#[derive(Debug)]
+pub struct TermSimilarity {
+    dict: Dictionary,
+    rows: Vec<Vec<(usize, f32)>>,
+}
+
+impl TermSimilarity {
+    fn with_dictionary(dict: Dictionary) -> TermSimilarity {
+        let mut rows: Vec<Vec<(usize, f32)>> = Vec::new();
+        rows.resize(dict.len(), Vec::new());
+
+        TermSimilarity {
+            dict,
+            rows,
+        }
+    }
+
+    fn insert(&mut self, i: usize, j: usize, value: f32) {
+        if i == j {
+            return;
+        }
+
+        self.rows[i].push((j, value));
+        self.rows[j].push((i, value));
+    }
+
+    fn sort(&mut self) {
+        for row in self.rows.iter_mut() {
+            row.sort_by_key(|&(j, _)| j);
+        }
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dict
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn nnz(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).sum()
+    }
+
+    pub fn similarity(&self, word1: &str, word2: &str) -> Option<f32> {
+        let i = self.dict.word_index(word1)? as usize;
+        let j = self.dict.word_index(word2)? as usize;
+
+        if i == j {
+            return Some(1.0);
+        }
+
+        match self.rows[i].binary_search_by_key(&j, |&(j, _)| j) {
+            Ok(pos) => Some(self.rows[i][pos].1),
+            Err(_) => Some(0.0),
+        }
+    }
+
+    // a^T S b
+    pub fn inner_product(&self, bow1: &[f32], bow2: &[f32]) -> f32 {
+        self.rows.iter()
+            .enumerate()
+            .filter(|&(i, _)| bow1[i] != 0.0)
+            .map(|(i, row)| {
+                let off_diagonal: f32 = row.iter()
+                    .map(|&(j, value)| value * bow2[j])
+                    .sum();
+
+                bow1[i] * (bow2[i] + off_diagonal)
+            })
+            .sum()
+    }
+
+    pub fn soft_cosine(&self, bow1: &[f32], bow2: &[f32]) -> Option<f32> {
+        let norm1 = self.inner_product(bow1, bow1);
+        let norm2 = self.inner_product(bow2, bow2);
+
+        if norm1 <= 0.0 || norm2 <= 0.0 {
+            return None;
+        }
+
+        Some(self.inner_product(bow1, bow2) / (norm1 * norm2).sqrt())
+    }
+
+    // a^T S b over sparse bows, the cost depends on the doc sizes, not on the vocabulary
+    pub fn inner_product_sparse(&self, bow1: &SparseBow, bow2: &SparseBow) -> f32 {
+        bow1.entries().iter()
+            .filter(|&&(i, _)| i < self.rows.len())
+            .map(|&(i, weight)| weight * (bow2.get(i) + sparse_dot(&self.rows[i], bow2.entries())))
+            .sum()
+    }
+
+    pub fn soft_cosine_sparse(&self, bow1: &SparseBow, bow2: &SparseBow) -> Option<f32> {
+        let norm1 = self.inner_product_sparse(bow1, bow1);
+        let norm2 = self.inner_product_sparse(bow2, bow2);
+
+        if norm1 <= 0.0 || norm2 <= 0.0 {
+            return None;
+        }
+
+        Some(self.inner_product_sparse(bow1, bow2) / (norm1 * norm2).sqrt())
+    }
+
+    // words count, words in index order, then "i j value" lines for the upper triangle
+    pub fn save<W>(&self, writer: &mut W) -> io::Result<()>
+        where W: Write
+    {
+        writeln!(writer, "{}", self.len())?;
+
+        for word in self.dict.iter() {
+            writeln!(writer, "{}", word)?;
+        }
+
+        for (i, row) in self.rows.iter().enumerate() {
+            for &(j, value) in row.iter().filter(|&&(j, _)| i < j) {
+                writeln!(writer, "{} {} {}", i, j, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load<R>(reader: R) -> io::Result<TermSimilarity>
+        where R: BufRead
+    {
+        let mut lines = reader.lines();
+
+        let count: usize = match lines.next() {
+            Some(line) => line?.trim().parse().map_err(|_| invalid("wrong words count"))?,
+            None => return Err(invalid("empty term similarity")),
+        };
+
+        let mut words: Vec<String> = Vec::with_capacity(count);
+        for _ in 0..count {
+            match lines.next() {
+                Some(line) => words.push(line?.trim_end_matches('\r').to_string()),
+                None => return Err(invalid("unexpected end of words")),
+            }
+        }
+
+        let dict = Dictionary::with_extend(&words);
+        if dict.len() != count || dict.iter().zip(words.iter()).any(|(w1, w2)| w1 != w2) {
+            return Err(invalid("words are not unique or not sorted"));
+        }
+
+        let mut similarity = TermSimilarity::with_dictionary(dict);
+
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+
+            if fields.is_empty() {
+                continue;
+            }
+
+            if fields.len() != 3 {
+                return Err(invalid("wrong term similarity entry"));
+            }
+
+            let i: usize = fields[0].parse().map_err(|_| invalid("wrong row index"))?;
+            let j: usize = fields[1].parse().map_err(|_| invalid("wrong column index"))?;
+            let value: f32 = fields[2].parse().map_err(|_| invalid("wrong similarity value"))?;
+
+            if i >= count || j >= count {
+                return Err(invalid("index out of range"));
+            }
+
+            if i >= j {
+                return Err(invalid("entry out of the upper triangle"));
+            }
+
+            similarity.insert(i, j, value);
+        }
+
+        similarity.sort();
+
+        if similarity.rows.iter().any(|row| row.windows(2).any(|pair| pair[0].0 == pair[1].0)) {
+            return Err(invalid("duplicate term similarity entry"));
+        }
+
+        Ok(similarity)
+    }
+}
+
+impl<'a> WordVector<'a> {
+    fn term_similarity_value(&self, word1: &str, word2: &str, threshold: f32, exponent: f32) -> Option<f32> {
+        if word1 == word2 {
+            return Some(1.0);
+        }
+
+        let similarity = self.words_similarity(word1, word2)?.max(0.0).powf(exponent);
+
+        if similarity > threshold {
+            Some(similarity)
+        } else {
+            None
+        }
+    }
+
+    pub fn term_similarity<T>(&self, vocabulary: &[T], threshold: f32, exponent: f32) -> TermSimilarity
+        where
+            T: ToString
+    {
+        let mut dict = self.dictionary(vocabulary);
+        dict.reindex();
+
+        let words: Vec<String> = dict.iter().cloned().collect();
+        let mut similarity = TermSimilarity::with_dictionary(dict);
+
+        for (i, word1) in words.iter().enumerate() {
+            for (j, word2) in words.iter().enumerate().skip(i + 1) {
+                if let Some(value) = self.term_similarity_value(word1, word2, threshold, exponent) {
+                    similarity.insert(i, j, value);
+                }
+            }
+        }
+
+        similarity.sort();
+
+        similarity
+    }
+
+    pub fn soft_cosine<T>(&self, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+        where
+            T: ToString
+    {
+        self.soft_cosine_with_coverage(doc1, doc2).map(|(similarity, _, _)| similarity)
+    }
+
+    pub fn soft_cosine_with_coverage<T>(&self, doc1: &[T], doc2: &[T]) -> Result<(f32, Coverage, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage1 = self.check_coverage(doc1)?;
+        let coverage2 = self.check_coverage(doc2)?;
+
+        Ok((self.dense_soft_cosine(doc1, doc2)?, coverage1, coverage2))
+    }
+
+    fn dense_soft_cosine<T>(&self, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+        where
+            T: ToString
+    {
+        let dict1 = self.dictionary(doc1);
+        let dict2 = self.dictionary(doc2);
+
+        if dict1.is_empty() || dict2.is_empty() {
+            return Err("empty dictionary");
+        }
+
+        let dict = dict1.join(&dict2);
+
+        let doc_bow1 = match dict.bow_normalized(doc1) {
+            Some(bow) => bow,
+            None => return Err("empty doc bow"),
+        };
+
+        let doc_bow2 = match dict.bow_normalized(doc2) {
+            Some(bow) => bow,
+            None => return Err("empty doc bow"),
+        };
+
+        let matrix = self.words_matrix(&dict, |word1, word2| {
+            self.term_similarity_value(word1, word2, TERM_SIMILARITY_THRESHOLD, TERM_SIMILARITY_EXPONENT)
+        });
+
+        let inner_product = |bow1: &[f32], bow2: &[f32]| -> f32 {
+            matrix.iter_rows()
+                .zip(bow1.iter())
+                .map(|(row, v1)| v1 * kernels::dot(row, bow2))
+                .sum()
+        };
+
+        let norm1 = inner_product(&doc_bow1, &doc_bow1);
+        let norm2 = inner_product(&doc_bow2, &doc_bow2);
+
+        if norm1 <= 0.0 || norm2 <= 0.0 {
+            return Err("zero doc norm");
+        }
+
+        Ok(inner_product(&doc_bow1, &doc_bow2) / (norm1 * norm2).sqrt())
+    }
+
+    pub fn soft_cosine_with<T>(&self, similarity: &TermSimilarity, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+        where
+            T: ToString
+    {
+        self.soft_cosine_with_coverage_using(similarity, doc1, doc2).map(|(value, _, _)| value)
+    }
+
+    // soft_cosine_with and the coverage of both docs
+    pub fn soft_cosine_with_coverage_using<T>(&self, similarity: &TermSimilarity, doc1: &[T], doc2: &[T]) -> Result<(f32, Coverage, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage1 = self.check_coverage(doc1)?;
+        let coverage2 = self.check_coverage(doc2)?;
+
+        Ok((self.sparse_soft_cosine(similarity, doc1, doc2)?, coverage1, coverage2))
+    }
+
+    fn sparse_soft_cosine<T>(&self, similarity: &TermSimilarity, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+        where
+            T: ToString
+    {
+        let doc_bow1 = match similarity.dictionary().bow_normalized_sparse(doc1) {
+            Some(bow) => bow,
+            None => return Err("empty doc bow"),
+        };
+
+        let doc_bow2 = match similarity.dictionary().bow_normalized_sparse(doc2) {
+            Some(bow) => bow,
+            None => return Err("empty doc bow"),
+        };
+
+        match similarity.soft_cosine_sparse(&doc_bow1, &doc_bow2) {
+            Some(value) => Ok(value),
+            None => Err("zero doc norm"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use testing::model::TestModel;
+
+    #[test]
+    fn test_term_similarity() {
+        let model = TestModel::default();
+        let vector = WordVector::new(&model, &model);
+
+        let similarity = vector.term_similarity(&["намело", "сугробы", "у", "калитки"], 0.0, 1.0);
+
+        assert_eq!(similarity.len(), 3, "check vocabulary size");
+        assert_eq!(similarity.nnz(), 6, "check non zero count");
+        assert_eq!(similarity.similarity("у", "у"), Some(1.0), "check diagonal");
+        assert_eq!(similarity.similarity("намело", "сугробы"), vector.words_similarity("намело", "сугробы"), "check value");
+        assert_eq!(similarity.similarity("намело", "калитки"), None, "check unknown word");
+
+        let doc1 = ["намело", "сугробы", "намело"];
+        let doc2 = ["у", "сугробы", "калитки"];
+        let dict = similarity.dictionary();
+
+        let dense = similarity.soft_cosine(&dict.bow_normalized(&doc1).unwrap(), &dict.bow_normalized(&doc2).unwrap());
+        let sparse = similarity.soft_cosine_sparse(&dict.bow_normalized_sparse(&doc1).unwrap(),
+                                                   &dict.bow_normalized_sparse(&doc2).unwrap());
+        assert!((dense.unwrap() - sparse.unwrap()).abs() < 1e-6, "check sparse soft cosine");
+
+        let sparse = vector.term_similarity(&["намело", "сугробы", "у"], 0.99, 1.0);
+        assert_eq!(sparse.nnz(), 0, "check threshold");
+    }
+
+    #[test]
+    fn test_term_similarity_save_load() {
+        let model = TestModel::default();
+        let vector = WordVector::new(&model, &model);
+
+        let similarity = vector.term_similarity(&["намело", "сугробы", "у", "нашего", "крыльца"], 0.7, 2.0);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        similarity.save(&mut buffer).unwrap();
+
+        let exist = TermSimilarity::load(buffer.as_slice()).unwrap();
+
+        assert_eq!(exist.dictionary(), similarity.dictionary(), "check dictionary");
+        assert_eq!(exist.nnz(), similarity.nnz(), "check non zero count");
+        assert_eq!(exist.similarity("у", "крыльца"), similarity.similarity("у", "крыльца"), "check value");
+
+        assert!(TermSimilarity::load("2\nу\nа\n".as_bytes()).is_err(), "check unsorted words");
+        assert!(TermSimilarity::load("2\nа\nу\n0 2 0.5\n".as_bytes()).is_err(), "check index range");
+    }
+
+    #[test]
+    fn test_term_similarity_load_validation() {
+        assert!(TermSimilarity::load("2\nа\nу\n1 0 0.5\n".as_bytes()).is_err(), "check lower triangle");
+        assert!(TermSimilarity::load("2\nа\nу\n0 0 0.5\n".as_bytes()).is_err(), "check diagonal");
+        assert!(TermSimilarity::load("2\nа\nу\n0 1 0.5\n0 1 0.5\n".as_bytes()).is_err(), "check duplicate");
+
+        let similarity = TermSimilarity::load("2\nа\nу\n0 1 0.5\n".as_bytes()).unwrap();
+
+        assert_eq!(similarity.nnz(), 2, "check symmetric entry");
+        assert_eq!(similarity.similarity("у", "а"), Some(0.5), "check loaded value");
+    }
+}
@@ -0,0 +1,182 @@
+use kernels;
+use model::Embeddings;
+use utils::sentence_mean;
+use WordVectorModel;
+
+pub const BOW: char = '<';
+pub const EOW: char = '>';
+
+// character n-grams of "<word>" with length in [minn, maxn], single boundary chars are skipped
+pub fn ngrams(word: &str, minn: usize, maxn: usize) -> Vec<String> {
+    let chars: Vec<char> = Some(BOW).into_iter()
+        .chain(word.chars())
+        .chain(Some(EOW))
+        .collect();
+
+    let mut result: Vec<String> = Vec::new();
+
+    for i in 0..chars.len() {
+        for n in minn.max(1)..maxn + 1 {
+            if i + n > chars.len() {
+                break;
+            }
+
+            if n == 1 && (i == 0 || i + n == chars.len()) {
+                continue;
+            }
+
+            result.push(chars[i..i + n].iter().collect());
+        }
+    }
+
+    result
+}
+
+// FNV-1a
+pub fn ngram_hash(ngram: &str) -> u32 {
+    ngram.bytes().fold(2_166_136_261u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(16_777_619)
+    })
+}
+
+pub fn ngram_buckets(word: &str, minn: usize, maxn: usize, bucket: usize) -> Vec<usize> {
+    if bucket == 0 || maxn == 0 {
+        return Vec::new();
+    }
+
+    ngrams(word, minn, maxn).iter()
+        .map(|ngram| ngram_hash(ngram) as usize % bucket)
+        .collect()
+}
+
+// fastText-like model, a word vector is the mean of its own vector and its n-gram vectors,
+// unseen words are represented by n-grams only
+#[derive(Debug)]
+pub struct SubwordEmbeddings {
+    words: Embeddings,
+    ngrams: Vec<f32>,
+    bucket: usize,
+    minn: usize,
+    maxn: usize,
+}
+
+impl SubwordEmbeddings {
+    pub fn new(words: Embeddings, ngrams: Vec<f32>, minn: usize, maxn: usize) -> Result<SubwordEmbeddings, &'static str> {
+        let dim = words.dim();
+
+        if dim == 0 || !ngrams.len().is_multiple_of(dim) {
+            return Err("n-gram vectors size mismatch");
+        }
+
+        if minn > maxn {
+            return Err("wrong n-gram length");
+        }
+
+        Ok(SubwordEmbeddings {
+            bucket: ngrams.len() / dim,
+            words,
+            ngrams,
+            minn,
+            maxn,
+        })
+    }
+
+    pub fn dim(&self) -> usize {
+        self.words.dim()
+    }
+
+    pub fn bucket(&self) -> usize {
+        self.bucket
+    }
+
+    pub fn words(&self) -> &Embeddings {
+        &self.words
+    }
+
+    pub fn ngram_vector(&self, bucket: usize) -> &[f32] {
+        let dim = self.dim();
+
+        &self.ngrams[bucket * dim..(bucket + 1) * dim]
+    }
+
+    pub fn subword_vector(&self, word: &str) -> Option<Vec<f32>> {
+        let mut vector: Vec<f32> = vec![0.0; self.dim()];
+        let mut count = 0;
+
+        if let Some(word_vector) = self.words.word_vector(word) {
+            kernels::axpy(1.0, word_vector, &mut vector);
+
+            count += 1;
+        }
+
+        for bucket in ngram_buckets(word, self.minn, self.maxn, self.bucket) {
+            kernels::axpy(1.0, self.ngram_vector(bucket), &mut vector);
+
+            count += 1;
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        vector.iter_mut()
+            .for_each(|v| *v /= count as f32);
+
+        Some(vector)
+    }
+}
+
+impl WordVectorModel for SubwordEmbeddings {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.words.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.subword_vector(word)
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        sentence_mean(text, self.dim(), |word| self.subword_vector(word))
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(ngrams("снег", 3, 4), ["<сн", "<сне", "сне", "снег", "нег", "нег>", "ег>"], "check n-grams");
+        assert_eq!(ngrams("у", 1, 2), ["<у", "у", "у>"], "check boundary chars");
+        assert!(ngrams("у", 4, 5).is_empty(), "check short word");
+    }
+
+    #[test]
+    fn test_ngram_buckets() {
+        let buckets = ngram_buckets("сугробы", 3, 6, 100);
+
+        assert_eq!(buckets.len(), ngrams("сугробы", 3, 6).len(), "check buckets count");
+        assert!(buckets.iter().all(|&bucket| bucket < 100), "check buckets range");
+        assert_eq!(ngram_hash(""), 2_166_136_261, "check hash offset basis");
+        assert!(ngram_buckets("сугробы", 3, 6, 0).is_empty(), "check no buckets");
+    }
+
+    #[test]
+    fn test_subword_embeddings() {
+        let words = Embeddings::with_vectors(&["снег"], 2, vec![1.0, 1.0]).unwrap();
+        let ngrams = vec![0.0f32; 2 * 8];
+
+        let model = SubwordEmbeddings::new(words, ngrams, 3, 3).unwrap();
+
+        assert_eq!(model.bucket(), 8, "check bucket");
+        assert_eq!(model.word_index("снег"), Some(0), "check word index");
+        assert_eq!(model.word_index("снега"), None, "check unknown word index");
+
+        // "<сн", "сне", "нег", "ег>" have zero vectors
+        assert_eq!(model.word_to_vector("снег"), Some(vec![0.2, 0.2]), "check word vector");
+        assert_eq!(model.word_to_vector("снега"), Some(vec![0.0, 0.0]), "check unseen word vector");
+
+        let words = Embeddings::with_vectors(&["снег"], 2, vec![1.0, 1.0]).unwrap();
+        assert!(SubwordEmbeddings::new(words, vec![0.0; 3], 3, 3).is_err(), "check size");
+    }
+}
@@ -1,6 +1,6 @@
-extern crate test;
-
 #[cfg(test)]
-mod model;
+pub(crate) mod model;
+// the assertions predate clippy, they are kept as they were written
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants, clippy::needless_borrows_for_generic_args)]
 mod wordvector;
\ No newline at end of file
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 
-use ::{WordVectorModel, WordVectorDistance};
-use ::utils::vec_sum;
+use ::{WordVectorModel, WordVectorDistance, CostLayout};
+use ::vecops;
 
 pub(crate) struct TestModel {
     data: BTreeMap<String, i64>,
@@ -35,35 +35,27 @@ impl Default for TestModel {
 
 impl WordVectorModel for TestModel {
     fn word_index(&self, word: &str) -> Option<i64> {
-        match self.data.get(&word.to_string()) {
-            Some(&index) => Some(index),
-            None => None
-        }
+        self.data.get(word).copied()
     }
 
     fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
-        match self.vectors.get(&word.to_string()) {
-            Some(index) => Some(index.clone()),
-            None => None,
-        }
+        self.vectors.get(word).cloned()
     }
 
     fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
-        let doc_vec: Vec<f32> = vec_sum(
-            text.split_whitespace()
-                .filter_map(|word| self.word_to_vector(&word))
-        );
+        let vectors: Vec<Vec<f32>> = text.split_whitespace()
+            .filter_map(|word| self.word_to_vector(word))
+            .collect();
 
-        if doc_vec.len() > 0 {
-            Some(doc_vec)
-        } else {
-            None
-        }
+        let mut doc_vec = vec![0.0f32; vectors.first()?.len()];
+        vecops::sum(&mut doc_vec, &vectors).ok()?;
+
+        Some(doc_vec)
     }
 }
 
 impl WordVectorDistance for TestModel {
-    fn calc(&self, doc_bow1: &[f32], doc_bow2: &[f32], distance_matrix: &[f32]) -> f32 {
+    fn calc(&self, _layout: CostLayout, doc_bow1: &[f32], doc_bow2: &[f32], distance_matrix: &[f32]) -> f32 {
         doc_bow1[1] * doc_bow2[2] * distance_matrix[2*4 + 3]
     }
 }
@@ -1,6 +1,10 @@
 use dictionary::Dictionary;
+use encoder::{MaxPooling, MinMaxPooling, Sum};
+use metric::Metric;
+use sif::{Sif, SIF_DEFAULT_A};
+use softcosine::{TERM_SIMILARITY_EXPONENT, TERM_SIMILARITY_THRESHOLD};
 use utils::doc_parse;
-use {WordVector};
+use {WordVector, WordMetric, WordVectorDistance, CostLayout};
 
 use testing::model::TestModel;
 
@@ -67,6 +71,16 @@ fn test_wordvector_wm_distance() {
     assert_eq!(exist_distance, expected_distance, "check distance");
 }
 
+#[test]
+fn test_wordvector_empty_doc_vector() {
+    let model = TestModel::default();
+    let vector = WordVector::new(&model, &model);
+
+    assert_eq!(vector.doc_to_vector(&doc_parse("за калиткой")), Err("empty doc vector"), "check unknown doc");
+    assert_eq!(vector.similarity(&doc_parse("за калиткой"), &doc_parse("намело сугробы")), Err("empty doc vector"),
+               "check similarity with unknown doc");
+}
+
 #[test]
 fn test_wordvector_similarity() {
     let model = TestModel::default();
@@ -85,4 +99,265 @@ fn test_wordvector_similarity() {
     let expected_similarity = 0.8433072f32;
 
     assert_eq!(exist_similarity, expected_similarity, "check similarity value");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_wordvector_sif_similarity() {
+    let model = TestModel::default();
+
+    let mut sif = Sif::with_vocabulary_order(SIF_DEFAULT_A, 5);
+
+    let corpus = vec![
+        doc_parse("намело сугробы"),
+        doc_parse("у нашего крыльца"),
+        doc_parse("сугробы у крыльца"),
+        doc_parse("намело у нашего"),
+    ];
+
+    if let Err(err) = sif.fit(&model, &corpus) {
+        assert!(false, "failed to fit sif {:?}", err);
+    }
+
+    let vector = WordVector::new(&model, &model).with_encoder(&sif);
+
+    let same = match vector.similarity(&doc_parse("намело сугробы"), &doc_parse("сугробы намело")) {
+        Ok(similarity) => similarity,
+        Err(err) => {
+            assert!(false, "failed to calc sif similarity {:?}", err);
+            0.0f32
+        }
+    };
+
+    assert!((same - 1.0).abs() < 1e-5, "check same doc similarity");
+
+    assert!(vector.similarity(&doc_parse("намело"), &doc_parse("за калиткой")).is_err(), "check empty doc");
+}
+
+#[test]
+fn test_wordvector_encoder() {
+    let model = TestModel::default();
+
+    {
+        let vector = WordVector::new(&model, &model).with_encoder(&MaxPooling);
+
+        match vector.doc_to_vector(&doc_parse("намело сугробы за калиткой")) {
+            Ok(exist) => assert_eq!(exist[0], 0.536739f32, "check max pooling doc vector"),
+            Err(err) => assert!(false, "failed to calc doc vector {:?}", err),
+        }
+    }
+
+    {
+        let vector = WordVector::new(&model, &model).with_encoder(&MinMaxPooling);
+
+        match vector.doc_to_vector(&doc_parse("намело сугробы")) {
+            Ok(exist) => assert_eq!(exist.len(), 20, "check min max doc vector"),
+            Err(err) => assert!(false, "failed to calc doc vector {:?}", err),
+        }
+    }
+
+    {
+        let mean = WordVector::new(&model, &model);
+        let sum = WordVector::new(&model, &model).with_encoder(&Sum);
+
+        let doc1 = doc_parse("намело сугробы");
+        let doc2 = doc_parse("сугробы у крыльца");
+
+        let exist = sum.similarity(&doc1, &doc2).unwrap();
+        let expected = mean.similarity(&doc1, &doc2).unwrap();
+
+        assert!((exist - expected).abs() < 1e-6, "check sum and mean cosine");
+    }
+}
+
+#[test]
+fn test_wordvector_soft_cosine() {
+    let model = TestModel::default();
+    let vector = WordVector::new(&model, &model);
+
+    let doc1 = doc_parse("намело сугробы");
+    let doc2 = doc_parse("сугробы у крыльца");
+
+    let exist = match vector.soft_cosine(&doc1, &doc2) {
+        Ok(similarity) => similarity,
+        Err(err) => {
+            assert!(false, "failed to calc soft cosine {:?}", err);
+            0.0f32
+        }
+    };
+
+    assert!(exist > 0.0 && exist < 1.0, "check soft cosine range");
+
+    let same = vector.soft_cosine(&doc1, &doc_parse("сугробы намело")).unwrap();
+    assert!((same - 1.0).abs() < 1e-6, "check same doc soft cosine");
+
+    let similarity = vector.term_similarity(
+        &doc_parse("намело сугробы у нашего крыльца"),
+        TERM_SIMILARITY_THRESHOLD,
+        TERM_SIMILARITY_EXPONENT
+    );
+
+    let precomputed = match vector.soft_cosine_with(&similarity, &doc1, &doc2) {
+        Ok(similarity) => similarity,
+        Err(err) => {
+            assert!(false, "failed to calc soft cosine {:?}", err);
+            0.0f32
+        }
+    };
+
+    assert!((exist - precomputed).abs() < 1e-6, "check precomputed term similarity");
+
+    assert!(vector.soft_cosine(&doc1, &doc_parse("за калиткой")).is_err(), "check empty doc");
+}
+
+#[test]
+fn test_wordvector_metric() {
+    let model = TestModel::default();
+
+    let doc1 = doc_parse("намело сугробы");
+    let doc2 = doc_parse("сугробы у крыльца");
+
+    let manhattan = |vec1: &[f32], vec2: &[f32]| -> f32 {
+        vec1.iter()
+            .zip(vec2.iter())
+            .map(|(v1, v2)| (v1 - v2).abs())
+            .sum()
+    };
+
+    let metrics: Vec<(&dyn WordMetric, &str)> = vec![
+        (&Metric::Euclidean, "euclidean"),
+        (&Metric::SquaredEuclidean, "squared euclidean"),
+        (&Metric::Cosine, "cosine"),
+        (&Metric::Angular, "angular"),
+        (&Metric::Manhattan, "manhattan"),
+        (&manhattan, "closure"),
+    ];
+
+    let mut distances: Vec<f32> = Vec::new();
+
+    for (metric, name) in metrics {
+        let vector = WordVector::new(&model, &model).with_metric(metric);
+
+        let words_distance = vector.words_distance("сугробы", "у").unwrap();
+
+        let exist = match vector.wm_distance(&doc1, &doc2) {
+            Ok(distance) => distance,
+            Err(err) => {
+                assert!(false, "failed to calc {} distance {:?}", name, err);
+                0.0f32
+            }
+        };
+
+        // the test solver picks [сугробы][у] of the cost matrix
+        let expected = 0.5f32 * (1.0 / 3.0) * words_distance;
+
+        assert_eq!(exist, expected, "check {} wm distance", name);
+
+        distances.push(exist);
+    }
+
+    assert_eq!(distances[0], 0.28105024f32, "check default euclidean");
+    assert_eq!(distances[4], distances[5], "check closure metric");
+    assert!(distances[2] < distances[0], "check cosine distance effect");
+    assert!(distances[1] > distances[0], "check squared euclidean effect");
+}
+
+// expected cost of the independent coupling, equal for every layout
+struct LayoutSolver;
+
+impl WordVectorDistance for LayoutSolver {
+    fn calc(&self, layout: CostLayout, doc_bow1: &[f32], doc_bow2: &[f32], distance_matrix: &[f32]) -> f32 {
+        let (rows, cols) = (doc_bow1.len(), doc_bow2.len());
+
+        assert_eq!(distance_matrix.len(), rows * cols, "check matrix size");
+
+        match layout {
+            CostLayout::Compact => {}
+            _ => assert_eq!(rows, cols, "check square matrix"),
+        }
+
+        if layout == CostLayout::Symmetric {
+            for i in 0..rows {
+                assert_eq!(distance_matrix[i * cols + i], 0.0, "check diagonal");
+
+                for j in 0..cols {
+                    assert_eq!(distance_matrix[i * cols + j], distance_matrix[j * cols + i], "check symmetric");
+
+                    if i != j {
+                        assert!(distance_matrix[i * cols + j] > 0.0, "check full matrix");
+                    }
+                }
+            }
+        }
+
+        let mut cost = 0.0f32;
+
+        for i in 0..rows {
+            for j in 0..cols {
+                cost += doc_bow1[i] * doc_bow2[j] * distance_matrix[i * cols + j];
+            }
+        }
+
+        cost
+    }
+}
+
+#[test]
+fn test_wordvector_cost_layout() {
+    let model = TestModel::default();
+
+    let doc1 = doc_parse("намело сугробы у");
+    let doc2 = doc_parse("сугробы у нашего крыльца");
+
+    let layouts = [CostLayout::Partial, CostLayout::Symmetric, CostLayout::Compact];
+
+    let distances: Vec<f32> = layouts.iter()
+        .map(|&layout| {
+            let vector = WordVector::new(&model, &LayoutSolver).with_cost_layout(layout);
+
+            match vector.wm_distance(&doc1, &doc2) {
+                Ok(distance) => distance,
+                Err(err) => {
+                    assert!(false, "failed to calc {:?} distance {:?}", layout, err);
+                    0.0f32
+                }
+            }
+        })
+        .collect();
+
+    assert!(distances[0] > 0.0, "check non zero distance");
+    assert!((distances[0] - distances[1]).abs() < 1e-6, "check symmetric layout");
+    assert!((distances[0] - distances[2]).abs() < 1e-6, "check compact layout");
+}
+
+#[test]
+fn test_wordvector_document_matrices() {
+    let model = TestModel::default();
+    let vector = WordVector::new(&model, &model);
+
+    let docs = vec![
+        doc_parse("намело сугробы"),
+        doc_parse("сугробы у крыльца"),
+        doc_parse("у крыльца"),
+    ];
+
+    let distances = vector.distance_matrix(&docs).unwrap();
+    let similarities = vector.similarity_matrix(&docs).unwrap();
+
+    assert_eq!(distances.shape(), (3, 3), "check distance matrix shape");
+
+    for i in 0..3 {
+        assert_eq!(distances[(i, i)], 0.0, "check zero self distance");
+        assert!((similarities[(i, i)] - 1.0).abs() < 1e-5, "check self similarity");
+
+        for j in 0..3 {
+            let expected = vector.similarity(&docs[i], &docs[j]).unwrap();
+            assert!((similarities[(i, j)] - expected).abs() < 1e-5, "check similarity [{}][{}]", i, j);
+
+            let vec1 = vector.doc_to_vector(&docs[i]).unwrap();
+            let vec2 = vector.doc_to_vector(&docs[j]).unwrap();
+            assert!((distances[(i, j)] - Metric::Euclidean.distance(&vec1, &vec2)).abs() < 1e-5, "check distance [{}][{}]", i, j);
+        }
+    }
+
+    assert!(vector.distance_matrix(&[doc_parse("за калиткой")]).is_err(), "check empty doc");
+}
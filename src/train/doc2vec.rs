@@ -0,0 +1,526 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use corpus::{Corpus, Sentences, shard_range};
+use model::Embeddings;
+use train::{Rng, Vocabulary, UnigramTable, Shared, Rows, Frozen, Worker};
+use train::{learning_rate, init_vectors, run_threads, axpy};
+use utils::invalid;
+use WordVectorModel;
+
+#[derive(Debug, Clone)]
+pub struct TaggedDocument {
+    pub tag: String,
+    pub words: Vec<String>,
+}
+
+impl TaggedDocument {
+    pub fn new<S, T>(tag: S, words: &[T]) -> TaggedDocument
+        where
+            S: ToString,
+            T: ToString
+    {
+        TaggedDocument {
+            tag: tag.to_string(),
+            words: words.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+}
+
+// A corpus of the documents as sentences starting with the tag
+impl Corpus for [TaggedDocument] {
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a> {
+        let (start, end) = shard_range(self.len(), shard, shards);
+
+        Box::new(self[start..end].iter()
+            .map(|doc| Ok(Some(&doc.tag).into_iter().chain(doc.words.iter()).cloned().collect())))
+    }
+}
+
+impl Corpus for Vec<TaggedDocument> {
+    fn sentences<'a>(&'a self, shard: usize, shards: usize) -> Sentences<'a> {
+        self.as_slice().sentences(shard, shards)
+    }
+}
+
+// Sentences of a tagged corpus without the tags
+struct Untagged<'a, C: ?Sized + 'a>(&'a C);
+
+impl<'a, C> Corpus for Untagged<'a, C>
+    where C: Corpus + ?Sized
+{
+    fn sentences<'b>(&'b self, shard: usize, shards: usize) -> Sentences<'b> {
+        Box::new(self.0.sentences(shard, shards)
+            .map(|sentence| sentence.map(|words| words.into_iter().skip(1).collect())))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Doc2VecMode {
+    // distributed memory: the paragraph vector and context words predict the center word
+    Dm,
+    // distributed bag of words: the paragraph vector predicts every word of the document
+    Dbow,
+}
+
+#[derive(Debug, Clone)]
+pub struct Doc2Vec {
+    pub mode: Doc2VecMode,
+    // PV-DBOW trains word vectors with skip-gram simultaneously
+    pub dbow_words: bool,
+    pub dim: usize,
+    pub window: usize,
+    pub negative: usize,
+    pub min_count: u64,
+    pub sample: f32,
+    pub alpha: f32,
+    pub min_alpha: f32,
+    pub epochs: usize,
+    pub infer_epochs: usize,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Default for Doc2Vec {
+    fn default() -> Doc2Vec {
+        Doc2Vec {
+            mode: Doc2VecMode::Dm,
+            dbow_words: false,
+            dim: 100,
+            window: 5,
+            negative: 5,
+            min_count: 5,
+            sample: 1e-3,
+            alpha: 0.025,
+            min_alpha: 0.0001,
+            epochs: 10,
+            infer_epochs: 10,
+            threads: 4,
+            seed: 1,
+        }
+    }
+}
+
+// Trained word and paragraph vectors, sentence_to_vector infers a paragraph vector
+// for a new text with frozen word and output weights
+pub struct ParagraphVectors {
+    config: Doc2Vec,
+    words: Embeddings,
+    docs: Embeddings,
+    output: Vec<f32>,
+    table: UnigramTable,
+}
+
+// only the sizes, the weights and the noise table are too large to print
+impl fmt::Debug for ParagraphVectors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParagraphVectors")
+            .field("mode", &self.config.mode)
+            .field("dim", &self.config.dim)
+            .field("words", &self.words.len())
+            .field("docs", &self.docs.len())
+            .finish()
+    }
+}
+
+// Paragraph rows of the non-empty sentences by a stable sentence id,
+// the offset of the thread shard plus the position in the shard
+struct Tags {
+    names: Vec<String>,
+    rows: Vec<usize>,
+    offsets: Vec<usize>,
+    // words of the vocabulary in an epoch
+    words: usize,
+}
+
+impl Tags {
+    fn read<C>(corpus: &C, shards: usize, vocab: &Vocabulary) -> io::Result<Tags>
+        where C: Corpus + ?Sized
+    {
+        let mut index: BTreeMap<String, usize> = BTreeMap::new();
+        let mut tags = Tags {
+            names: Vec::new(),
+            rows: Vec::new(),
+            offsets: Vec::with_capacity(shards),
+            words: 0,
+        };
+
+        for shard in 0..shards {
+            tags.offsets.push(tags.rows.len());
+
+            for sentence in corpus.sentences(shard, shards) {
+                let sentence = sentence?;
+
+                if let Some((tag, words)) = sentence.split_first() {
+                    let row = match index.get(tag) {
+                        Some(&row) => row,
+                        None => {
+                            index.insert(tag.clone(), tags.names.len());
+                            tags.names.push(tag.clone());
+
+                            tags.names.len() - 1
+                        }
+                    };
+
+                    tags.rows.push(row);
+                    tags.words += vocab.encode(words).len();
+                }
+            }
+        }
+
+        Ok(tags)
+    }
+}
+
+struct Context<'a> {
+    vocab: &'a Vocabulary,
+    tags: &'a Tags,
+    table: &'a UnigramTable,
+    words: &'a Shared,
+    docs: &'a Shared,
+    output: &'a Shared,
+    processed: &'a AtomicUsize,
+    total: usize,
+}
+
+impl Doc2Vec {
+    // the first word of every sentence is the document tag, sentences with the same tag
+    // train one paragraph vector; empty sentences are skipped
+    pub fn train<C>(&self, corpus: &C) -> io::Result<ParagraphVectors>
+        where C: Corpus + ?Sized
+    {
+        if self.dim == 0 || self.window == 0 || self.threads == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong training parameters"));
+        }
+
+        let vocab = Vocabulary::build(&Untagged(corpus), self.min_count)?;
+        if vocab.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty vocabulary"));
+        }
+
+        let tags = Tags::read(corpus, self.threads, &vocab)?;
+
+        let table = UnigramTable::new(&vocab);
+
+        let mut rng = Rng::new(self.seed);
+        let words = Shared::new(init_vectors(vocab.len(), self.dim, &mut rng));
+        let docs = Shared::new(init_vectors(tags.names.len(), self.dim, &mut rng));
+        let output = Shared::new(vec![0.0f32; vocab.len() * self.dim]);
+
+        {
+            let processed = AtomicUsize::new(0);
+
+            let context = Context {
+                vocab: &vocab,
+                tags: &tags,
+                table: &table,
+                words: &words,
+                docs: &docs,
+                output: &output,
+                processed: &processed,
+                total: self.epochs * tags.words,
+            };
+
+            run_threads(self.threads, |id| self.train_thread(&context, corpus, id))?;
+        }
+
+        Ok(ParagraphVectors {
+            config: self.clone(),
+            words: Embeddings::with_vectors(vocab.words(), self.dim, words.into_vec()).map_err(invalid)?,
+            docs: Embeddings::with_vectors(&tags.names, self.dim, docs.into_vec()).map_err(invalid)?,
+            output: output.into_vec(),
+            table,
+        })
+    }
+
+    // every epoch streams the thread's corpus shard again
+    fn train_thread<C>(&self, context: &Context, corpus: &C, id: usize) -> io::Result<()>
+        where C: Corpus + ?Sized
+    {
+        let mut worker = Worker::new(self.seed + id as u64, self.dim);
+        let mut words: Vec<usize> = Vec::new();
+
+        let end = context.tags.offsets.get(id + 1).cloned().unwrap_or(context.tags.rows.len());
+
+        for _ in 0..self.epochs {
+            let mut sentence_id = context.tags.offsets[id];
+
+            for sentence in corpus.sentences(id, self.threads) {
+                let sentence = sentence?;
+
+                let doc = match sentence.split_first() {
+                    Some((_, words)) => context.vocab.encode(words),
+                    None => continue,
+                };
+
+                if sentence_id == end {
+                    return Err(invalid("corpus changed during training"));
+                }
+
+                let tag = context.tags.rows[sentence_id];
+                sentence_id += 1;
+
+                words.clear();
+                words.extend(doc.iter()
+                    .cloned()
+                    .filter(|&word| context.vocab.keep_probability(word, self.sample) >= worker.rng.next_f32()));
+
+                worker.alpha = learning_rate(self.alpha, self.min_alpha,
+                                             context.processed.load(Ordering::Relaxed), context.total);
+
+                for pos in 0..words.len() {
+                    let window = self.window - worker.rng.below(self.window);
+                    let range = (pos.saturating_sub(window), (pos + window + 1).min(words.len()));
+
+                    context.docs.read_row(tag, &mut worker.input);
+
+                    match self.mode {
+                        Doc2VecMode::Dm => {
+                            self.dm_hidden(context.words, &words, pos, range, &mut worker.input);
+
+                            worker.negative_sampling(words[pos], self.negative, context.table, context.output);
+
+                            context.docs.add_row(tag, 1.0, &worker.grad);
+                            for c in (range.0..range.1).filter(|&c| c != pos) {
+                                context.words.add_row(words[c], 1.0, &worker.grad);
+                            }
+                        }
+                        Doc2VecMode::Dbow => {
+                            worker.negative_sampling(words[pos], self.negative, context.table, context.output);
+                            context.docs.add_row(tag, 1.0, &worker.grad);
+
+                            if self.dbow_words {
+                                for c in (range.0..range.1).filter(|&c| c != pos) {
+                                    context.words.read_row(words[c], &mut worker.input);
+
+                                    worker.negative_sampling(words[pos], self.negative, context.table, context.output);
+                                    context.words.add_row(words[c], 1.0, &worker.grad);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                context.processed.fetch_add(doc.len(), Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+
+    // mean of the paragraph vector, already in hidden, and the context word vectors
+    fn dm_hidden(&self, word_vectors: &dyn Rows, words: &[usize], pos: usize, range: (usize, usize),
+                 hidden: &mut [f32]) {
+        for c in (range.0..range.1).filter(|&c| c != pos) {
+            word_vectors.add_to(words[c], 1.0, hidden);
+        }
+
+        let count = range.1 - range.0;
+        hidden.iter_mut().for_each(|v| *v /= count as f32);
+    }
+}
+
+impl ParagraphVectors {
+    pub fn words(&self) -> &Embeddings {
+        &self.words
+    }
+
+    pub fn docs(&self) -> &Embeddings {
+        &self.docs
+    }
+
+    pub fn doc_vector(&self, tag: &str) -> Option<&[f32]> {
+        self.docs.word_vector(tag)
+    }
+
+    // gradient descent on a new paragraph vector, word and output weights are frozen
+    pub fn infer_vector<T>(&self, doc: &[T]) -> Option<Vec<f32>>
+        where T: ToString
+    {
+        let config = &self.config;
+        let dim = config.dim;
+
+        let words: Vec<usize> = doc.iter()
+            .filter_map(|word| self.words.word_index(&word.to_string()))
+            .map(|index| index as usize)
+            .collect();
+
+        if words.is_empty() {
+            return None;
+        }
+
+        let mut worker = Worker::new(config.seed, dim);
+        let mut doc_vector = init_vectors(1, dim, &mut worker.rng);
+
+        let word_vectors = Frozen(self.words.matrix().as_slice());
+        let output = Frozen(&self.output);
+
+        let total = config.infer_epochs * words.len();
+
+        for epoch in 0..config.infer_epochs {
+            worker.alpha = learning_rate(config.alpha, config.min_alpha, epoch * words.len(), total);
+
+            for pos in 0..words.len() {
+                worker.input.copy_from_slice(&doc_vector);
+
+                if config.mode == Doc2VecMode::Dm {
+                    let window = config.window - worker.rng.below(config.window);
+                    let range = (pos.saturating_sub(window), (pos + window + 1).min(words.len()));
+
+                    config.dm_hidden(&word_vectors, &words, pos, range, &mut worker.input);
+                }
+
+                worker.negative_sampling(words[pos], config.negative, &self.table, &output);
+
+                axpy(1.0, &worker.grad, &mut doc_vector);
+            }
+        }
+
+        Some(doc_vector)
+    }
+}
+
+impl WordVectorModel for ParagraphVectors {
+    fn word_index(&self, word: &str) -> Option<i64> {
+        self.words.word_index(word)
+    }
+
+    fn word_to_vector(&self, word: &str) -> Option<Vec<f32>> {
+        self.words.word_to_vector(word)
+    }
+
+    fn sentence_to_vector(&self, text: &str) -> Option<Vec<f32>> {
+        let doc: Vec<&str> = text.split_whitespace().collect();
+
+        self.infer_vector(&doc)
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use corpus::TextCorpus;
+    use metric::Metric;
+    use train::word2vec::testing::topic_corpus;
+    use WordMetric;
+
+    fn tagged_corpus() -> Vec<TaggedDocument> {
+        topic_corpus().iter()
+            .enumerate()
+            .map(|(i, words)| TaggedDocument::new(format!("{}_{}", i % 2, i), words))
+            .collect()
+    }
+
+    // topic words are interleaved with common words, so a narrow context doesn't tell the topic
+    // and PV-DM has to keep it in the paragraph vector
+    fn interleaved_corpus() -> Vec<TaggedDocument> {
+        let common = ["и", "а", "но"];
+
+        topic_corpus().iter()
+            .enumerate()
+            .map(|(i, words)| {
+                let words: Vec<&str> = words.iter()
+                    .enumerate()
+                    .flat_map(|(k, word)| vec![word.as_str(), common[(i + k) % common.len()]])
+                    .collect();
+
+                TaggedDocument::new(format!("{}_{}", i % 2, i), &words)
+            })
+            .collect()
+    }
+
+    fn mean_distance(model: &ParagraphVectors, vector: &[f32], topic: usize) -> f32 {
+        let tags: Vec<String> = (0..40).filter(|i| i % 2 == topic).map(|i| format!("{}_{}", topic, i)).collect();
+
+        tags.iter()
+            .map(|tag| Metric::Cosine.distance(vector, model.doc_vector(tag).unwrap()))
+            .sum::<f32>() / tags.len() as f32
+    }
+
+    fn check_inference(config: Doc2Vec, corpus: &[TaggedDocument], text: &str) {
+        let model = config.train(corpus).unwrap();
+
+        assert_eq!(model.docs().len(), 400, "check paragraph vectors");
+
+        let exist = model.sentence_to_vector(text).unwrap();
+        assert_eq!(exist.len(), config.dim, "check dimension");
+        assert_eq!(Some(exist.clone()), model.sentence_to_vector(text), "check deterministic inference");
+
+        let same = mean_distance(&model, &exist, 0);
+        let other = mean_distance(&model, &exist, 1);
+
+        assert!(same < other, "check inferred vector is close to its topic {} < {}", same, other);
+
+        assert_eq!(model.sentence_to_vector("за калиткой"), None, "check unknown text");
+    }
+
+    #[test]
+    fn test_doc2vec_dm() {
+        let config = Doc2Vec {
+            dim: 16,
+            window: 1,
+            min_count: 1,
+            sample: 0.0,
+            alpha: 0.05,
+            epochs: 20,
+            infer_epochs: 50,
+            threads: 1,
+            ..Doc2Vec::default()
+        };
+
+        check_inference(config, &interleaved_corpus(), "снег и метель а мороз но снег и стужа а сугробы");
+    }
+
+    #[test]
+    fn test_doc2vec_dbow() {
+        let config = Doc2Vec {
+            mode: Doc2VecMode::Dbow,
+            dbow_words: true,
+            dim: 16,
+            window: 3,
+            min_count: 1,
+            sample: 0.0,
+            alpha: 0.05,
+            epochs: 20,
+            infer_epochs: 50,
+            threads: 1,
+            ..Doc2Vec::default()
+        };
+
+        check_inference(config, &tagged_corpus(), "снег метель мороз снег стужа сугробы");
+    }
+
+    #[test]
+    fn test_doc2vec_corpus_tags() {
+        let corpus = vec![
+            vec!["зима", "снег", "метель"],
+            vec!["лето", "жара", "зной"],
+            vec!["зима", "мороз", "стужа"],
+            vec![],
+        ];
+
+        let config = Doc2Vec {
+            dim: 4,
+            min_count: 1,
+            epochs: 1,
+            threads: 1,
+            ..Doc2Vec::default()
+        };
+
+        let model = config.train(&corpus).unwrap();
+
+        assert_eq!(model.docs().len(), 2, "check documents of one tag");
+        assert!(model.doc_vector("зима").is_some(), "check tag");
+        assert_eq!(model.words().len(), 6, "check tags are not words");
+        assert_eq!(model.words().word_index("зима"), None, "check tag is not a word");
+        assert_eq!(format!("{:?}", model), "ParagraphVectors { mode: Dm, dim: 4, words: 6, docs: 2 }", "check debug");
+
+        let parallel = Doc2Vec { threads: 3, ..config.clone() }.train(&corpus).unwrap();
+        assert_eq!(parallel.docs().words(), model.docs().words(), "check tags of shards");
+
+        let err = config.train(&TextCorpus::new("/nonexistent/corpus.txt")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound, "check missed file");
+    }
+}
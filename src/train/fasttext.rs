@@ -0,0 +1,217 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use corpus::Corpus;
+use model::Embeddings;
+use subword::{SubwordEmbeddings, ngram_buckets};
+use train::{Rng, Vocabulary, UnigramTable, Shared, Rows, Worker};
+use train::{learning_rate, init_vectors, run_threads};
+use utils::invalid;
+
+// fastText skip-gram, the input rows are words followed by `bucket` hashed n-grams
+#[derive(Debug, Clone)]
+pub struct FastText {
+    pub dim: usize,
+    pub window: usize,
+    pub negative: usize,
+    pub min_count: u64,
+    pub sample: f32,
+    pub alpha: f32,
+    pub min_alpha: f32,
+    pub epochs: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub minn: usize,
+    pub maxn: usize,
+    pub bucket: usize,
+}
+
+impl Default for FastText {
+    fn default() -> FastText {
+        FastText {
+            dim: 100,
+            window: 5,
+            negative: 5,
+            min_count: 5,
+            sample: 1e-4,
+            alpha: 0.05,
+            min_alpha: 0.0001,
+            epochs: 5,
+            threads: 4,
+            seed: 1,
+            minn: 3,
+            maxn: 6,
+            bucket: 2_000_000,
+        }
+    }
+}
+
+struct Context<'a> {
+    vocab: &'a Vocabulary,
+    subwords: &'a [Vec<usize>],
+    table: &'a UnigramTable,
+    input: &'a Shared,
+    output: &'a Shared,
+    processed: &'a AtomicUsize,
+    total: usize,
+}
+
+impl FastText {
+    pub fn train<C>(&self, corpus: &C) -> io::Result<SubwordEmbeddings>
+        where C: Corpus + ?Sized
+    {
+        if self.dim == 0 || self.window == 0 || self.threads == 0 || self.minn > self.maxn {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong training parameters"));
+        }
+
+        let vocab = Vocabulary::build(corpus, self.min_count)?;
+        if vocab.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty vocabulary"));
+        }
+
+        let subwords: Vec<Vec<usize>> = vocab.words().iter()
+            .enumerate()
+            .map(|(index, word)| {
+                Some(index).into_iter()
+                    .chain(ngram_buckets(word, self.minn, self.maxn, self.bucket)
+                        .into_iter()
+                        .map(|bucket| vocab.len() + bucket))
+                    .collect()
+            })
+            .collect();
+
+        let table = UnigramTable::new(&vocab);
+
+        let mut rng = Rng::new(self.seed);
+        let input = Shared::new(init_vectors(vocab.len() + self.bucket, self.dim, &mut rng));
+        let output = Shared::new(vec![0.0f32; vocab.len() * self.dim]);
+
+        {
+            let processed = AtomicUsize::new(0);
+
+            let context = Context {
+                vocab: &vocab,
+                subwords: &subwords,
+                table: &table,
+                input: &input,
+                output: &output,
+                processed: &processed,
+                total: self.epochs * vocab.total() as usize,
+            };
+
+            run_threads(self.threads, |id| self.train_thread(&context, corpus, id))?;
+        }
+
+        let mut input = input.into_vec();
+        let ngrams = input.split_off(vocab.len() * self.dim);
+        let words = Embeddings::with_vectors(vocab.words(), self.dim, input).map_err(invalid)?;
+
+        SubwordEmbeddings::new(words, ngrams, self.minn, self.maxn).map_err(invalid)
+    }
+
+    fn train_thread<C>(&self, context: &Context, corpus: &C, id: usize) -> io::Result<()>
+        where C: Corpus + ?Sized
+    {
+        let mut worker = Worker::new(self.seed + id as u64, self.dim);
+        let mut words: Vec<usize> = Vec::new();
+
+        for _ in 0..self.epochs {
+            for sentence in corpus.sentences(id, self.threads) {
+                let sentence = context.vocab.encode(&sentence?);
+                if sentence.is_empty() {
+                    continue;
+                }
+
+                words.clear();
+                words.extend(sentence.iter()
+                    .cloned()
+                    .filter(|&word| context.vocab.keep_probability(word, self.sample) >= worker.rng.next_f32()));
+
+                worker.alpha = learning_rate(self.alpha, self.min_alpha,
+                                          context.processed.load(Ordering::Relaxed), context.total);
+
+                for (pos, &word) in words.iter().enumerate() {
+                    let subwords = &context.subwords[word];
+
+                    let window = self.window - worker.rng.below(self.window);
+                    let (start, end) = (pos.saturating_sub(window), (pos + window + 1).min(words.len()));
+
+                    for c in (start..end).filter(|&c| c != pos) {
+                        worker.input.iter_mut().for_each(|v| *v = 0.0);
+
+                        for &row in subwords {
+                            context.input.add_to(row, 1.0, &mut worker.input);
+                        }
+
+                        worker.input.iter_mut().for_each(|v| *v /= subwords.len() as f32);
+
+                        worker.negative_sampling(words[c], self.negative, context.table, context.output);
+
+                        for &row in subwords {
+                            context.input.add_row(row, 1.0, &worker.grad);
+                        }
+                    }
+                }
+
+                context.processed.fetch_add(sentence.len(), Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use metric::Metric;
+    use train::word2vec::testing::{topic_corpus, check_topics};
+    use {WordMetric, WordVectorModel};
+
+    fn fasttext() -> FastText {
+        FastText {
+            dim: 16,
+            window: 3,
+            min_count: 1,
+            sample: 0.0,
+            epochs: 10,
+            threads: 1,
+            minn: 2,
+            maxn: 4,
+            bucket: 1000,
+            ..FastText::default()
+        }
+    }
+
+    #[test]
+    fn test_fasttext() {
+        let corpus = topic_corpus();
+
+        let model = fasttext().train(&corpus).unwrap();
+
+        assert_eq!(model.words().len(), 10, "check vocabulary");
+        assert_eq!(model.bucket(), 1000, "check bucket");
+        check_topics(&model);
+
+        let again = fasttext().train(&corpus).unwrap();
+        assert_eq!(model.words().as_slice(), again.words().as_slice(), "check reproducibility");
+        let err = FastText { minn: 5, maxn: 4, ..fasttext() }.train(&corpus).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "check parameters");
+    }
+
+    #[test]
+    fn test_fasttext_unseen_word() {
+        let corpus = topic_corpus();
+
+        let model = fasttext().train(&corpus).unwrap();
+
+        assert_eq!(model.word_index("метелью"), None, "check unseen word");
+
+        let vector = |word: &str| model.word_to_vector(word).unwrap();
+
+        let typo = Metric::Cosine.distance(&vector("метелью"), &vector("метель"));
+        let other = Metric::Cosine.distance(&vector("метелью"), &vector("пляж"));
+
+        assert!(typo < other, "check unseen word is close to its morphological form {} < {}", typo, other);
+    }
+}
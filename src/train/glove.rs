@@ -0,0 +1,662 @@
+use std::cmp::{self, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::env;
+
+use corpus::Corpus;
+use model::Embeddings;
+use train::{Rng, Vocabulary, Shared, Rows, init_vectors, dot, axpy};
+
+#[derive(Debug, Clone)]
+pub struct Glove {
+    pub dim: usize,
+    pub window: usize,
+    pub min_count: u64,
+    pub x_max: f32,
+    pub power: f32,
+    pub alpha: f32,
+    pub epochs: usize,
+    pub threads: usize,
+    pub seed: u64,
+    // approximate bytes of co-occurrence counts and shuffled entries held in memory,
+    // the rest is spilled to disk
+    pub memory_limit: usize,
+    pub spill_dir: Option<PathBuf>,
+    // word vectors are the sum of word and context vectors
+    pub sum_context: bool,
+}
+
+impl Default for Glove {
+    fn default() -> Glove {
+        Glove {
+            dim: 100,
+            window: 15,
+            min_count: 5,
+            x_max: 100.0,
+            power: 0.75,
+            alpha: 0.05,
+            epochs: 25,
+            threads: 4,
+            seed: 1,
+            memory_limit: 1 << 30,
+            spill_dir: None,
+            sum_context: true,
+        }
+    }
+}
+
+type Entry = (u32, u32, f32);
+
+const ENTRY_SIZE: usize = 12;
+
+// estimated bytes of a counter entry: the key, the value and B-tree node overhead
+const MAP_ENTRY_SIZE: usize = 32;
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn write_entry<W>(writer: &mut W, entry: Entry) -> io::Result<()>
+    where W: Write
+{
+    writer.write_all(&entry.0.to_le_bytes())?;
+    writer.write_all(&entry.1.to_le_bytes())?;
+    writer.write_all(&entry.2.to_bits().to_le_bytes())
+}
+
+fn read_entry<R>(reader: &mut R) -> io::Result<Option<Entry>>
+    where R: Read
+{
+    let mut buffer = [0u8; ENTRY_SIZE];
+
+    match reader.read_exact(&mut buffer) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let field = |pos: usize| u32::from_le_bytes([buffer[pos], buffer[pos + 1], buffer[pos + 2], buffer[pos + 3]]);
+
+    Ok(Some((field(0), field(4), f32::from_bits(field(8)))))
+}
+
+fn shuffle_slice<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i + 1));
+    }
+}
+
+// Temporary file of entries, removed on drop
+struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    fn open(&self, start: usize) -> io::Result<BufReader<File>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+
+        reader.seek(SeekFrom::Start((start * ENTRY_SIZE) as u64))?;
+
+        Ok(reader)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+struct SpillWriter {
+    file: SpillFile,
+    writer: BufWriter<File>,
+    len: usize,
+}
+
+impl SpillWriter {
+    fn create(dir: &Path) -> io::Result<SpillWriter> {
+        let path = dir.join(format!("glove-cooccurrence-{}-{}.bin",
+                                    process::id(), SPILL_COUNTER.fetch_add(1, Ordering::SeqCst)));
+
+        let writer = BufWriter::new(File::create(&path)?);
+
+        Ok(SpillWriter {
+            file: SpillFile { path },
+            writer,
+            len: 0,
+        })
+    }
+
+    fn push(&mut self, entry: Entry) -> io::Result<()> {
+        self.len += 1;
+
+        write_entry(&mut self.writer, entry)
+    }
+
+    fn finish(mut self) -> io::Result<Entries> {
+        self.writer.flush()?;
+
+        Ok(Entries::Disk(self.file, self.len))
+    }
+}
+
+// A sequence of entries, small ones stay in memory
+enum Entries {
+    Memory(Vec<Entry>),
+    Disk(SpillFile, usize),
+}
+
+impl Entries {
+    fn len(&self) -> usize {
+        match *self {
+            Entries::Memory(ref entries) => entries.len(),
+            Entries::Disk(_, len) => len,
+        }
+    }
+
+    // calls `f` on the entries start..end in order
+    fn each<F>(&self, start: usize, end: usize, mut f: F) -> io::Result<()>
+        where F: FnMut(Entry) -> io::Result<()>
+    {
+        match *self {
+            Entries::Memory(ref entries) => {
+                for &entry in entries[start..end].iter() {
+                    f(entry)?;
+                }
+            }
+            Entries::Disk(ref file, _) => {
+                let mut reader = file.open(start)?;
+
+                for _ in start..end {
+                    match read_entry(&mut reader)? {
+                        Some(entry) => f(entry)?,
+                        None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated spill file")),
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn entry(&self, pos: usize) -> io::Result<Entry> {
+        match *self {
+            Entries::Memory(ref entries) => Ok(entries[pos]),
+            Entries::Disk(ref file, _) => match read_entry(&mut file.open(pos)?)? {
+                Some(entry) => Ok(entry),
+                None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated spill file")),
+            },
+        }
+    }
+
+    // uniform random permutation holding at most `max_entries` in memory: chunks are shuffled
+    // and spilled, then the next entry is taken from a random chunk with probability
+    // proportional to the entries left in it
+    fn shuffle(&self, rng: &mut Rng, max_entries: usize, dir: &Path) -> io::Result<Entries> {
+        let mut chunk: Vec<Entry> = Vec::with_capacity(max_entries.min(self.len()));
+        let mut chunks: Vec<Entries> = Vec::new();
+
+        self.each(0, self.len(), |entry| {
+            chunk.push(entry);
+
+            if chunk.len() == max_entries && chunk.len() < self.len() {
+                shuffle_slice(&mut chunk, rng);
+
+                let mut writer = SpillWriter::create(dir)?;
+                for &entry in chunk.iter() {
+                    writer.push(entry)?;
+                }
+
+                chunks.push(writer.finish()?);
+                chunk.clear();
+            }
+
+            Ok(())
+        })?;
+
+        shuffle_slice(&mut chunk, rng);
+
+        if chunks.is_empty() {
+            return Ok(Entries::Memory(chunk));
+        }
+
+        let mut readers: Vec<(BufReader<File>, usize)> = Vec::with_capacity(chunks.len());
+        for run in chunks.iter() {
+            if let Entries::Disk(ref file, len) = *run {
+                readers.push((file.open(0)?, len));
+            }
+        }
+
+        let mut rest: Vec<Entry> = chunk;
+        let mut left = self.len();
+        let mut writer = SpillWriter::create(dir)?;
+
+        while left > 0 {
+            let mut pick = rng.below(left);
+            left -= 1;
+
+            if pick < rest.len() {
+                writer.push(rest.pop().expect("entry is left"))?;
+                continue;
+            }
+            pick -= rest.len();
+
+            for &mut (ref mut reader, ref mut count) in readers.iter_mut() {
+                if pick < *count {
+                    *count -= 1;
+
+                    match read_entry(reader)? {
+                        Some(entry) => writer.push(entry)?,
+                        None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated spill file")),
+                    }
+
+                    break;
+                }
+
+                pick -= *count;
+            }
+        }
+
+        writer.finish()
+    }
+}
+
+// Sparse co-occurrence matrix sorted by (row, column), kept on disk when it doesn't fit the memory limit
+pub struct Cooccurrence {
+    entries: Entries,
+}
+
+struct Counter<'a> {
+    map: BTreeMap<(u32, u32), f32>,
+    max_entries: usize,
+    spill_dir: &'a Path,
+    runs: Vec<Entries>,
+}
+
+impl<'a> Counter<'a> {
+    fn add(&mut self, i: u32, j: u32, value: f32) -> io::Result<()> {
+        *self.map.entry((i, j)).or_insert(0.0) += value;
+
+        if self.map.len() >= self.max_entries {
+            self.spill()?;
+        }
+
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let mut writer = SpillWriter::create(self.spill_dir)?;
+
+        for (&(i, j), &value) in self.map.iter() {
+            writer.push((i, j, value))?;
+        }
+
+        self.runs.push(writer.finish()?);
+        self.map.clear();
+
+        Ok(())
+    }
+
+    // sorted runs are merged into one file as a stream
+    fn merge(mut self) -> io::Result<Entries> {
+        if self.runs.is_empty() {
+            let map = mem::take(&mut self.map);
+
+            return Ok(Entries::Memory(map.into_iter().map(|((i, j), value)| (i, j, value)).collect()));
+        }
+
+        if !self.map.is_empty() {
+            self.spill()?;
+        }
+
+        let mut readers: Vec<BufReader<File>> = Vec::with_capacity(self.runs.len());
+        for run in self.runs.iter() {
+            if let Entries::Disk(ref file, _) = *run {
+                readers.push(file.open(0)?);
+            }
+        }
+
+        let mut heads: Vec<f32> = vec![0.0; readers.len()];
+        let mut heap: BinaryHeap<Reverse<((u32, u32), usize)>> = BinaryHeap::new();
+
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some((i, j, value)) = read_entry(reader)? {
+                heads[run] = value;
+                heap.push(Reverse(((i, j), run)));
+            }
+        }
+
+        let mut writer = SpillWriter::create(self.spill_dir)?;
+        let mut last: Option<Entry> = None;
+
+        while let Some(Reverse(((i, j), run))) = heap.pop() {
+            match last {
+                Some(ref mut last) if last.0 == i && last.1 == j => last.2 += heads[run],
+                _ => {
+                    if let Some(entry) = last {
+                        writer.push(entry)?;
+                    }
+
+                    last = Some((i, j, heads[run]));
+                }
+            }
+
+            if let Some((i, j, value)) = read_entry(&mut readers[run])? {
+                heads[run] = value;
+                heap.push(Reverse(((i, j), run)));
+            }
+        }
+
+        if let Some(entry) = last {
+            writer.push(entry)?;
+        }
+
+        writer.finish()
+    }
+}
+
+impl Cooccurrence {
+    // symmetric window, a pair at distance d adds 1/d in both directions,
+    // sentences are consumed as a stream, the counter holds about `memory_limit` bytes
+    pub fn count<I>(sentences: I, window: usize, memory_limit: usize, spill_dir: &Path) -> io::Result<Cooccurrence>
+        where I: IntoIterator<Item=io::Result<Vec<usize>>>
+    {
+        let mut counter = Counter {
+            map: BTreeMap::new(),
+            max_entries: (memory_limit / MAP_ENTRY_SIZE).max(1),
+            spill_dir,
+            runs: Vec::new(),
+        };
+
+        for sentence in sentences {
+            let sentence = sentence?;
+
+            for (pos, &word) in sentence.iter().enumerate() {
+                for distance in 1..window + 1 {
+                    if distance > pos {
+                        break;
+                    }
+
+                    let context = sentence[pos - distance];
+                    let value = 1.0 / distance as f32;
+
+                    counter.add(word as u32, context as u32, value)?;
+                    counter.add(context as u32, word as u32, value)?;
+                }
+            }
+        }
+
+        Ok(Cooccurrence {
+            entries: counter.merge()?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // binary search, one read per step for a matrix on disk
+    pub fn get(&self, i: usize, j: usize) -> io::Result<Option<f32>> {
+        let key = (i as u32, j as u32);
+        let (mut low, mut high) = (0, self.len());
+
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let entry = self.entries.entry(middle)?;
+
+            match (entry.0, entry.1).cmp(&key) {
+                cmp::Ordering::Less => low = middle + 1,
+                cmp::Ordering::Greater => high = middle,
+                cmp::Ordering::Equal => return Ok(Some(entry.2)),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+struct Parameters {
+    words: Shared,
+    contexts: Shared,
+    word_biases: Shared,
+    context_biases: Shared,
+    words_grad: Shared,
+    contexts_grad: Shared,
+    word_biases_grad: Shared,
+    context_biases_grad: Shared,
+}
+
+impl Glove {
+    pub fn train<C>(&self, corpus: &C) -> io::Result<Embeddings>
+        where C: Corpus + ?Sized
+    {
+        if self.dim == 0 || self.window == 0 || self.threads == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong training parameters"));
+        }
+
+        let vocab = Vocabulary::build(corpus, self.min_count)?;
+        if vocab.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty vocabulary"));
+        }
+
+        let encoded = corpus.all_sentences()
+            .map(|sentence| sentence.map(|sentence| vocab.encode(&sentence)));
+
+        let cooccurrence = Cooccurrence::count(encoded, self.window, self.memory_limit, &self.spill_dir())?;
+
+        self.optimize(&vocab, &cooccurrence)
+    }
+
+    fn spill_dir(&self) -> PathBuf {
+        match self.spill_dir {
+            Some(ref dir) => dir.clone(),
+            None => env::temp_dir(),
+        }
+    }
+
+    // every epoch trains on a new shuffle of the entries, each thread reads its part of it
+    pub fn optimize(&self, vocab: &Vocabulary, cooccurrence: &Cooccurrence) -> io::Result<Embeddings> {
+        let (n, dim) = (vocab.len(), self.dim);
+        let (max_entries, spill_dir) = ((self.memory_limit / ENTRY_SIZE).max(1), self.spill_dir());
+
+        let mut rng = Rng::new(self.seed);
+
+        let params = Parameters {
+            words: Shared::new(init_vectors(n, dim, &mut rng)),
+            contexts: Shared::new(init_vectors(n, dim, &mut rng)),
+            word_biases: Shared::new(init_vectors(n, 1, &mut rng)),
+            context_biases: Shared::new(init_vectors(n, 1, &mut rng)),
+            words_grad: Shared::new(vec![1.0f32; n * dim]),
+            contexts_grad: Shared::new(vec![1.0f32; n * dim]),
+            word_biases_grad: Shared::new(vec![1.0f32; n]),
+            context_biases_grad: Shared::new(vec![1.0f32; n]),
+        };
+
+        for _ in 0..self.epochs {
+            let shuffled = cooccurrence.entries.shuffle(&mut rng, max_entries, &spill_dir)?;
+            let chunk_size = shuffled.len().div_ceil(self.threads).max(1);
+
+            let (params, shuffled) = (&params, &shuffled);
+
+            let results: Vec<io::Result<()>> = thread::scope(|scope| {
+                let handles: Vec<thread::ScopedJoinHandle<io::Result<()>>> = (0..self.threads)
+                    .map(|id| {
+                        let start = (id * chunk_size).min(shuffled.len());
+                        let end = (start + chunk_size).min(shuffled.len());
+
+                        scope.spawn(move || {
+                            let mut rows = (vec![0.0f32; dim], vec![0.0f32; dim]);
+
+                            shuffled.each(start, end, |entry| {
+                                self.update(params, entry, &mut rows);
+                                Ok(())
+                            })
+                        })
+                    })
+                    .collect();
+
+                handles.into_iter()
+                    .map(|handle| handle.join().expect("training thread panicked"))
+                    .collect()
+            });
+
+            for result in results {
+                result?;
+            }
+        }
+
+        let mut words = params.words.into_vec();
+        let contexts = params.contexts.into_vec();
+
+        if self.sum_context {
+            axpy(1.0, &contexts, &mut words);
+        }
+
+        Ok(Embeddings::with_vectors(vocab.words(), dim, words)
+            .expect("vectors match vocabulary"))
+    }
+
+    // AdaGrad step on f(x) * (w_i . c_j + b_i + b_j - ln x)^2,
+    // rows are buffers of dim values for the copies of w_i and c_j
+    fn update(&self, params: &Parameters, entry: Entry, rows: &mut (Vec<f32>, Vec<f32>)) {
+        let (i, j, x) = (entry.0 as usize, entry.1 as usize, entry.2);
+        let dim = self.dim;
+
+        let (word, context) = (i * dim, j * dim);
+
+        params.words.read_row(i, &mut rows.0);
+        params.contexts.read_row(j, &mut rows.1);
+
+        let diff = dot(&rows.0, &rows.1) + params.word_biases.get(i) + params.context_biases.get(j) - x.ln();
+
+        let weight = if x < self.x_max { (x / self.x_max).powf(self.power) } else { 1.0 };
+        let fdiff = weight * diff;
+
+        if !fdiff.is_finite() {
+            return;
+        }
+
+        for k in 0..dim {
+            let (w, c) = (rows.0[k], rows.1[k]);
+            let (g_word, g_context) = (fdiff * c, fdiff * w);
+
+            let word_grad = params.words_grad.get(word + k);
+            let context_grad = params.contexts_grad.get(context + k);
+
+            params.words.set(word + k, w - self.alpha * g_word / word_grad.sqrt());
+            params.contexts.set(context + k, c - self.alpha * g_context / context_grad.sqrt());
+
+            params.words_grad.set(word + k, word_grad + g_word * g_word);
+            params.contexts_grad.set(context + k, context_grad + g_context * g_context);
+        }
+
+        let word_bias_grad = params.word_biases_grad.get(i);
+        let context_bias_grad = params.context_biases_grad.get(j);
+
+        params.word_biases.set(i, params.word_biases.get(i) - self.alpha * fdiff / word_bias_grad.sqrt());
+        params.context_biases.set(j, params.context_biases.get(j) - self.alpha * fdiff / context_bias_grad.sqrt());
+
+        params.word_biases_grad.set(i, word_bias_grad + fdiff * fdiff);
+        params.context_biases_grad.set(j, context_bias_grad + fdiff * fdiff);
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use train::word2vec::testing::{topic_corpus, check_topics};
+
+    #[test]
+    fn test_cooccurrence_count() {
+        let sentences = vec![vec![0usize, 1, 2, 1]];
+
+        let exist = Cooccurrence::count(sentences.into_iter().map(Ok), 2, 1000, &env::temp_dir()).unwrap();
+
+        assert_eq!(exist.get(0, 1).unwrap(), Some(1.0), "check adjacent words");
+        assert_eq!(exist.get(2, 0).unwrap(), Some(0.5), "check distance weight");
+        assert_eq!(exist.get(1, 1).unwrap(), Some(1.0), "check same word counted in both directions");
+        assert_eq!(exist.get(0, 0).unwrap(), None, "check missed pair");
+        assert_eq!(exist.get(1, 2).unwrap(), exist.get(2, 1).unwrap(), "check symmetric");
+    }
+
+    #[test]
+    fn test_cooccurrence_spill() {
+        let sentences: Vec<Vec<usize>> = (0..50)
+            .map(|i| (0..10).map(|j| (i * 7 + j * 3) % 13).collect())
+            .collect();
+
+        let in_memory = Cooccurrence::count(sentences.iter().cloned().map(Ok), 3, 1_000_000, &env::temp_dir()).unwrap();
+        let spilled = Cooccurrence::count(sentences.iter().cloned().map(Ok), 3, 7 * MAP_ENTRY_SIZE, &env::temp_dir()).unwrap();
+
+        assert_eq!(spilled.len(), in_memory.len(), "check merged entries");
+
+        for pos in 0..spilled.len() {
+            let (e1, e2) = (spilled.entries.entry(pos).unwrap(), in_memory.entries.entry(pos).unwrap());
+
+            assert_eq!((e1.0, e1.1), (e2.0, e2.1), "check merged order");
+            assert!((e1.2 - e2.2).abs() < 1e-4, "check merged value");
+        }
+
+        assert_eq!(spilled.get(3, 5).unwrap(), in_memory.get(3, 5).unwrap(), "check lookup on disk");
+        assert_eq!(spilled.get(13, 0).unwrap(), None, "check missed pair on disk");
+    }
+
+    #[test]
+    fn test_cooccurrence_shuffle() {
+        let entries = Entries::Memory((0..100).map(|i| (i, 0, 1.0)).collect());
+        let mut rng = Rng::new(1);
+
+        let mut shuffles: Vec<Vec<u32>> = Vec::new();
+
+        for &max_entries in [1000, 7].iter() {
+            let shuffled = entries.shuffle(&mut rng, max_entries, &env::temp_dir()).unwrap();
+            let mut rows: Vec<u32> = Vec::new();
+
+            shuffled.each(0, shuffled.len(), |entry| {
+                rows.push(entry.0);
+                Ok(())
+            }).unwrap();
+
+            shuffles.push(rows);
+        }
+
+        assert!(shuffles.iter().all(|rows| rows[..] != (0..100).collect::<Vec<u32>>()[..]), "check shuffled");
+
+        for rows in shuffles.iter_mut() {
+            rows.sort();
+            assert_eq!(rows[..], (0..100).collect::<Vec<u32>>()[..], "check permutation");
+        }
+    }
+
+    #[test]
+    fn test_glove() {
+        let corpus = topic_corpus();
+
+        let config = Glove {
+            dim: 16,
+            window: 3,
+            min_count: 1,
+            x_max: 10.0,
+            epochs: 30,
+            threads: 1,
+            memory_limit: 20 * MAP_ENTRY_SIZE,
+            ..Glove::default()
+        };
+
+        let model = config.train(&corpus).unwrap();
+
+        assert_eq!(model.len(), 10, "check vocabulary");
+        check_topics(&model);
+
+        let again = config.train(&corpus).unwrap();
+        assert_eq!(model.as_slice(), again.as_slice(), "check reproducibility");
+    }
+}
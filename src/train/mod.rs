@@ -0,0 +1,481 @@
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::thread;
+
+use corpus::Corpus;
+use dictionary::Dictionary;
+
+pub mod word2vec;
+pub mod glove;
+pub mod fasttext;
+pub mod doc2vec;
+
+const UNIGRAM_TABLE_SIZE: usize = 1_000_000;
+const UNIGRAM_POWER: f64 = 0.75;
+
+// Linear congruential generator of the original word2vec, cheap and reproducible per thread
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(25_214_903_917).wrapping_add(11);
+        self.0
+    }
+
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() & 0xFFFF) as f32 / 65_536.0
+    }
+
+    pub fn below(&mut self, n: usize) -> usize {
+        ((self.next_u64() >> 16) % n as u64) as usize
+    }
+}
+
+// Training vocabulary over a corpus dictionary, with the subsampling of frequent words
+pub struct Vocabulary {
+    dict: Dictionary,
+    total: u64,
+}
+
+impl Vocabulary {
+    // words are sorted by count descending, ties alphabetically
+    pub fn build<C>(corpus: &C, min_count: u64) -> io::Result<Vocabulary>
+        where C: Corpus + ?Sized
+    {
+        let mut dict = Dictionary::with_corpus(corpus)?;
+
+        dict.prune_min_count(min_count);
+        dict.reindex_by_frequency();
+
+        Ok(Vocabulary {
+            total: dict.total(),
+            dict,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.dict.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn words(&self) -> &[String] {
+        self.dict.words()
+    }
+
+    pub fn index(&self, word: &str) -> Option<usize> {
+        self.dict.word_index(word).map(|index| index as usize)
+    }
+
+    pub fn count(&self, index: usize) -> u64 {
+        self.dict.count(index as i64).unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub(crate) fn encode<T>(&self, sentence: &[T]) -> Vec<usize>
+        where T: ToString
+    {
+        sentence.iter()
+            .filter_map(|word| self.index(&word.to_string()))
+            .collect()
+    }
+
+    // probability to keep a word with frequent words subsampling
+    pub(crate) fn keep_probability(&self, index: usize, sample: f32) -> f32 {
+        if sample <= 0.0 {
+            return 1.0;
+        }
+
+        let threshold = sample as f64 * self.total as f64;
+        let count = self.count(index) as f64;
+
+        (((count / threshold).sqrt() + 1.0) * threshold / count) as f32
+    }
+}
+
+pub(crate) struct UnigramTable {
+    table: Vec<u32>,
+}
+
+impl UnigramTable {
+    pub fn new(vocab: &Vocabulary) -> UnigramTable {
+        let norm: f64 = (0..vocab.len())
+            .map(|i| (vocab.count(i) as f64).powf(UNIGRAM_POWER))
+            .sum();
+
+        let mut table: Vec<u32> = Vec::with_capacity(UNIGRAM_TABLE_SIZE);
+        let mut cumulative = 0.0f64;
+
+        for i in 0..vocab.len() {
+            cumulative += (vocab.count(i) as f64).powf(UNIGRAM_POWER) / norm;
+
+            let end = ((cumulative * UNIGRAM_TABLE_SIZE as f64) as usize).min(UNIGRAM_TABLE_SIZE);
+
+            while table.len() < end {
+                table.push(i as u32);
+            }
+        }
+
+        while table.len() < UNIGRAM_TABLE_SIZE {
+            table.push(vocab.len().saturating_sub(1) as u32);
+        }
+
+        UnigramTable {
+            table,
+        }
+    }
+
+    pub fn sample(&self, rng: &mut Rng) -> usize {
+        self.table[rng.below(self.table.len())] as usize
+    }
+}
+
+// Parameters shared between training threads without locks (Hogwild),
+// concurrent updates of the same value may be lost and it is fine for SGD.
+// Values are f32 bits in relaxed atomics, so threads never race on plain memory
+pub(crate) struct Shared {
+    data: Vec<AtomicU32>,
+}
+
+impl Shared {
+    pub fn new(data: Vec<f32>) -> Shared {
+        Shared {
+            data: data.into_iter().map(|v| AtomicU32::new(v.to_bits())).collect(),
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<f32> {
+        self.data.into_iter().map(|v| f32::from_bits(v.into_inner())).collect()
+    }
+
+    pub fn get(&self, pos: usize) -> f32 {
+        f32::from_bits(self.data[pos].load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, pos: usize, value: f32) {
+        self.data[pos].store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+// Weight rows of out.len() or x.len() values
+pub(crate) trait Rows {
+    // out = row
+    fn read_row(&self, index: usize, out: &mut [f32]);
+    // out += a * row
+    fn add_to(&self, index: usize, a: f32, out: &mut [f32]);
+    // row += a * x
+    fn add_row(&self, index: usize, a: f32, x: &[f32]);
+}
+
+impl Rows for Shared {
+    fn read_row(&self, index: usize, out: &mut [f32]) {
+        let start = index * out.len();
+
+        for (k, v) in out.iter_mut().enumerate() {
+            *v = self.get(start + k);
+        }
+    }
+
+    fn add_to(&self, index: usize, a: f32, out: &mut [f32]) {
+        let start = index * out.len();
+
+        for (k, v) in out.iter_mut().enumerate() {
+            *v += a * self.get(start + k);
+        }
+    }
+
+    fn add_row(&self, index: usize, a: f32, x: &[f32]) {
+        let start = index * x.len();
+
+        for (k, v) in x.iter().enumerate() {
+            self.set(start + k, self.get(start + k) + a * v);
+        }
+    }
+}
+
+// Weights of a trained model, updates are dropped; used to infer new input vectors
+pub(crate) struct Frozen<'a>(pub &'a [f32]);
+
+impl<'a> Rows for Frozen<'a> {
+    fn read_row(&self, index: usize, out: &mut [f32]) {
+        let dim = out.len();
+
+        out.copy_from_slice(&self.0[index * dim..(index + 1) * dim]);
+    }
+
+    fn add_to(&self, index: usize, a: f32, out: &mut [f32]) {
+        let dim = out.len();
+
+        axpy(a, &self.0[index * dim..(index + 1) * dim], out);
+    }
+
+    fn add_row(&self, _index: usize, _a: f32, _x: &[f32]) {}
+}
+
+// runs `task(id)` on `threads` threads, a thread usually trains on the corpus shard `id`;
+// the error of the first failed thread is returned
+pub(crate) fn run_threads<F>(threads: usize, task: F) -> io::Result<()>
+    where F: Fn(usize) -> io::Result<()> + Sync
+{
+    let task = &task;
+
+    let results: Vec<io::Result<()>> = thread::scope(|scope| {
+        let handles: Vec<thread::ScopedJoinHandle<io::Result<()>>> = (0..threads)
+            .map(|id| scope.spawn(move || task(id)))
+            .collect();
+
+        handles.into_iter()
+            .map(|handle| handle.join().expect("training thread panicked"))
+            .collect()
+    });
+
+    results.into_iter().collect()
+}
+
+pub(crate) fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+pub(crate) use kernels::{dot, axpy};
+
+pub(crate) fn learning_rate(alpha: f32, min_alpha: f32, processed: usize, total: usize) -> f32 {
+    let progress = processed as f32 / (total as f32 + 1.0);
+
+    (alpha * (1.0 - progress)).max(min_alpha)
+}
+
+// State of a training thread: random numbers, learning rate and buffers of dim values
+pub(crate) struct Worker {
+    pub rng: Rng,
+    pub alpha: f32,
+    // input of the output layer: a copy of an input row or the hidden layer
+    pub input: Vec<f32>,
+    // gradient of the input after an output layer update
+    pub grad: Vec<f32>,
+    // copy of the output row being updated
+    row: Vec<f32>,
+}
+
+impl Worker {
+    pub fn new(seed: u64, dim: usize) -> Worker {
+        Worker {
+            rng: Rng::new(seed),
+            alpha: 0.0,
+            input: vec![0.0; dim],
+            grad: vec![0.0; dim],
+            row: vec![0.0; dim],
+        }
+    }
+
+    // one positive and `negative` noise targets, updates output rows and computes the input gradient
+    pub fn negative_sampling(&mut self, target: usize, negative: usize, table: &UnigramTable, output: &dyn Rows) {
+        self.grad.iter_mut().for_each(|v| *v = 0.0);
+
+        for d in 0..negative + 1 {
+            let (word, label) = if d == 0 {
+                (target, 1.0f32)
+            } else {
+                let word = table.sample(&mut self.rng);
+
+                if word == target {
+                    continue;
+                }
+
+                (word, 0.0f32)
+            };
+
+            self.output_step(output, word, label);
+        }
+    }
+
+    // walks the path of the word in the Huffman tree, updates inner node rows and computes the input gradient
+    pub fn hierarchical_softmax(&mut self, target: usize, tree: &HuffmanTree, output: &dyn Rows) {
+        self.grad.iter_mut().for_each(|v| *v = 0.0);
+
+        for (&code, &point) in tree.code(target).iter().zip(tree.points(target).iter()) {
+            self.output_step(output, point, 1.0 - code as f32);
+        }
+    }
+
+    fn output_step(&mut self, output: &dyn Rows, index: usize, label: f32) {
+        output.read_row(index, &mut self.row);
+
+        let g = (label - sigmoid(dot(&self.input, &self.row))) * self.alpha;
+
+        axpy(g, &self.row, &mut self.grad);
+        output.add_row(index, g, &self.input);
+    }
+}
+
+// Huffman tree over the vocabulary counts built as in the original word2vec,
+// codes and inner node points go from the root to the word
+pub(crate) struct HuffmanTree {
+    codes: Vec<Vec<u8>>,
+    points: Vec<Vec<usize>>,
+}
+
+impl HuffmanTree {
+    pub fn new(vocab: &Vocabulary) -> HuffmanTree {
+        let n = vocab.len();
+
+        let mut codes: Vec<Vec<u8>> = Vec::with_capacity(n);
+        let mut points: Vec<Vec<usize>> = Vec::with_capacity(n);
+
+        if n < 2 {
+            codes.resize(n, Vec::new());
+            points.resize(n, Vec::new());
+
+            return HuffmanTree {
+                codes,
+                points,
+            };
+        }
+
+        let mut count: Vec<u64> = (0..n).map(|i| vocab.count(i)).collect();
+        count.resize(2 * n, u64::MAX);
+
+        let mut binary: Vec<u8> = vec![0; 2 * n];
+        let mut parent: Vec<usize> = vec![0; 2 * n];
+
+        // vocabulary is sorted by count descending, so leaves are consumed from the end
+        let mut pos1 = n as isize - 1;
+        let mut pos2 = n;
+
+        let mut next_min = |count: &Vec<u64>| -> usize {
+            if pos1 >= 0 && count[pos1 as usize] < count[pos2] {
+                pos1 -= 1;
+                (pos1 + 1) as usize
+            } else {
+                pos2 += 1;
+                pos2 - 1
+            }
+        };
+
+        for a in 0..n - 1 {
+            let min1 = next_min(&count);
+            let min2 = next_min(&count);
+
+            count[n + a] = count[min1] + count[min2];
+            parent[min1] = n + a;
+            parent[min2] = n + a;
+            binary[min2] = 1;
+        }
+
+        let root = 2 * n - 2;
+
+        for a in 0..n {
+            let mut code: Vec<u8> = Vec::new();
+            let mut point: Vec<usize> = Vec::new();
+
+            let mut b = a;
+            while b != root {
+                code.push(binary[b]);
+                b = parent[b];
+                point.push(b - n);
+            }
+
+            code.reverse();
+            point.reverse();
+
+            codes.push(code);
+            points.push(point);
+        }
+
+        HuffmanTree {
+            codes,
+            points,
+        }
+    }
+
+    pub fn inner_nodes(&self) -> usize {
+        self.codes.len().saturating_sub(1)
+    }
+
+    pub fn code(&self, word: usize) -> &[u8] {
+        &self.codes[word]
+    }
+
+    pub fn points(&self, word: usize) -> &[usize] {
+        &self.points[word]
+    }
+}
+
+pub(crate) fn init_vectors(len: usize, dim: usize, rng: &mut Rng) -> Vec<f32> {
+    (0..len * dim)
+        .map(|_| (rng.next_f32() - 0.5) / dim as f32)
+        .collect()
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_vocabulary() {
+        let sentences = vec![
+            vec!["намело", "сугробы", "у", "крыльца"],
+            vec!["сугробы", "у", "нашего", "крыльца"],
+            vec!["сугробы", "за", "калиткой"],
+        ];
+
+        let vocab = Vocabulary::build(&sentences, 2).unwrap();
+
+        assert_eq!(vocab.words(), ["сугробы", "крыльца", "у"], "check min count and order");
+        assert_eq!(vocab.total(), 7, "check total");
+        assert_eq!(vocab.index("у"), Some(2), "check index");
+        assert_eq!(vocab.encode(&["у", "намело", "сугробы"]), [2, 0], "check encode");
+        assert!(vocab.keep_probability(0, 0.1) < vocab.keep_probability(1, 0.1), "check subsampling");
+        assert_eq!(vocab.keep_probability(0, 0.0), 1.0, "check no subsampling");
+    }
+
+    #[test]
+    fn test_huffman_tree() {
+        let sentences = vec![
+            vec!["a"; 8],
+            vec!["b"; 4],
+            vec!["c"; 2],
+            vec!["d"; 1],
+        ];
+        let vocab = Vocabulary::build(&sentences, 1).unwrap();
+
+        let tree = HuffmanTree::new(&vocab);
+
+        assert_eq!(tree.inner_nodes(), 3, "check inner nodes");
+        assert_eq!(tree.code(0).len(), 1, "check frequent word code");
+        assert_eq!(tree.code(3).len(), 3, "check rare word code");
+
+        for word in 0..vocab.len() {
+            assert_eq!(tree.points(word)[0], 2, "check path starts at root");
+            assert_eq!(tree.code(word).len(), tree.points(word).len(), "check path length");
+
+            for other in 0..vocab.len() {
+                if word != other {
+                    assert!(!tree.code(other).starts_with(tree.code(word)), "check prefix free codes");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unigram_table() {
+        let sentences = vec![vec!["a"; 16], vec!["b"; 1]];
+        let vocab = Vocabulary::build(&sentences, 1).unwrap();
+
+        let table = UnigramTable::new(&vocab);
+        let mut rng = Rng::new(1);
+
+        let count = (0..10000).filter(|_| table.sample(&mut rng) == 1).count();
+
+        // 1 / (16^0.75 + 1) = 1/9
+        assert!(count > 900 && count < 1300, "check noise distribution {}", count);
+    }
+}
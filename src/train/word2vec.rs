@@ -0,0 +1,311 @@
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use corpus::Corpus;
+use model::Embeddings;
+use train::{Rng, Vocabulary, UnigramTable, HuffmanTree, Shared, Rows, Worker};
+use train::{learning_rate, init_vectors, run_threads};
+use utils::invalid;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Architecture {
+    SkipGram,
+    Cbow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Loss {
+    NegativeSampling,
+    HierarchicalSoftmax,
+}
+
+// threads == 1 gives reproducible vectors for a seed
+#[derive(Debug, Clone)]
+pub struct Word2Vec {
+    pub architecture: Architecture,
+    pub loss: Loss,
+    pub dim: usize,
+    pub window: usize,
+    pub negative: usize,
+    pub min_count: u64,
+    pub sample: f32,
+    pub alpha: f32,
+    pub min_alpha: f32,
+    pub epochs: usize,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Default for Word2Vec {
+    fn default() -> Word2Vec {
+        Word2Vec {
+            architecture: Architecture::SkipGram,
+            loss: Loss::NegativeSampling,
+            dim: 100,
+            window: 5,
+            negative: 5,
+            min_count: 5,
+            sample: 1e-3,
+            alpha: 0.025,
+            min_alpha: 0.0001,
+            epochs: 5,
+            threads: 4,
+            seed: 1,
+        }
+    }
+}
+
+enum Output {
+    Negative(UnigramTable),
+    Hierarchical(HuffmanTree),
+}
+
+struct Context<'a> {
+    vocab: &'a Vocabulary,
+    output_layer: &'a Output,
+    input: &'a Shared,
+    output: &'a Shared,
+    processed: &'a AtomicUsize,
+    total: usize,
+}
+
+impl Word2Vec {
+    pub fn train<C>(&self, corpus: &C) -> io::Result<Embeddings>
+        where C: Corpus + ?Sized
+    {
+        if self.dim == 0 || self.window == 0 || self.threads == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "wrong training parameters"));
+        }
+
+        let vocab = Vocabulary::build(corpus, self.min_count)?;
+        if vocab.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty vocabulary"));
+        }
+
+        let (output_layer, output_size) = match self.loss {
+            Loss::NegativeSampling => (Output::Negative(UnigramTable::new(&vocab)), vocab.len()),
+            Loss::HierarchicalSoftmax => {
+                let tree = HuffmanTree::new(&vocab);
+                let inner_nodes = tree.inner_nodes();
+
+                (Output::Hierarchical(tree), inner_nodes)
+            },
+        };
+
+        let mut rng = Rng::new(self.seed);
+        let input = Shared::new(init_vectors(vocab.len(), self.dim, &mut rng));
+        let output = Shared::new(vec![0.0f32; output_size.max(1) * self.dim]);
+
+        {
+            let processed = AtomicUsize::new(0);
+
+            let context = Context {
+                vocab: &vocab,
+                output_layer: &output_layer,
+                input: &input,
+                output: &output,
+                processed: &processed,
+                total: self.epochs * vocab.total() as usize,
+            };
+
+            run_threads(self.threads, |id| self.train_thread(&context, corpus, id))?;
+        }
+
+        Embeddings::with_vectors(vocab.words(), self.dim, input.into_vec()).map_err(invalid)
+    }
+
+    fn update(&self, context: &Context, target: usize, worker: &mut Worker) {
+        match *context.output_layer {
+            Output::Negative(ref table) => worker.negative_sampling(target, self.negative, table, context.output),
+            Output::Hierarchical(ref tree) => worker.hierarchical_softmax(target, tree, context.output),
+        }
+    }
+
+    fn skip_gram(&self, context: &Context, words: &[usize], pos: usize, window: (usize, usize), worker: &mut Worker) {
+        let (start, end) = window;
+
+        for c in (start..end).filter(|&c| c != pos) {
+            context.input.read_row(words[c], &mut worker.input);
+
+            self.update(context, words[pos], worker);
+
+            context.input.add_row(words[c], 1.0, &worker.grad);
+        }
+    }
+
+    // the hidden layer is the mean of the context vectors, its gradient goes to every context word
+    fn cbow(&self, context: &Context, words: &[usize], pos: usize, window: (usize, usize), worker: &mut Worker) {
+        let (start, end) = window;
+
+        let count = end - start - 1;
+        if count == 0 {
+            return;
+        }
+
+        worker.input.iter_mut().for_each(|v| *v = 0.0);
+
+        for c in (start..end).filter(|&c| c != pos) {
+            context.input.add_to(words[c], 1.0, &mut worker.input);
+        }
+
+        worker.input.iter_mut().for_each(|v| *v /= count as f32);
+
+        self.update(context, words[pos], worker);
+
+        for c in (start..end).filter(|&c| c != pos) {
+            context.input.add_row(words[c], 1.0, &worker.grad);
+        }
+    }
+
+    // every epoch streams the thread's corpus shard again
+    fn train_thread<C>(&self, context: &Context, corpus: &C, id: usize) -> io::Result<()>
+        where C: Corpus + ?Sized
+    {
+        let mut worker = Worker::new(self.seed + id as u64, self.dim);
+        let mut words: Vec<usize> = Vec::new();
+
+        for _ in 0..self.epochs {
+            for sentence in corpus.sentences(id, self.threads) {
+                let sentence = context.vocab.encode(&sentence?);
+                if sentence.is_empty() {
+                    continue;
+                }
+
+                words.clear();
+                words.extend(sentence.iter()
+                    .cloned()
+                    .filter(|&word| context.vocab.keep_probability(word, self.sample) >= worker.rng.next_f32()));
+
+                worker.alpha = learning_rate(self.alpha, self.min_alpha,
+                                          context.processed.load(Ordering::Relaxed), context.total);
+
+                for pos in 0..words.len() {
+                    let window = self.window - worker.rng.below(self.window);
+                    let range = (pos.saturating_sub(window), (pos + window + 1).min(words.len()));
+
+                    match self.architecture {
+                        Architecture::SkipGram =>
+                            self.skip_gram(context, &words, pos, range, &mut worker),
+                        Architecture::Cbow =>
+                            self.cbow(context, &words, pos, range, &mut worker),
+                    }
+                }
+
+                context.processed.fetch_add(sentence.len(), Ordering::Relaxed);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use WordVectorModel;
+    use metric::Metric;
+    use WordMetric;
+
+    // two topics, words of a topic share contexts
+    pub(crate) fn topic_corpus() -> Vec<Vec<String>> {
+        let topics = [
+            ["снег", "сугробы", "метель", "мороз", "стужа"],
+            ["солнце", "жара", "пляж", "море", "песок"],
+        ];
+
+        let mut rng = Rng::new(7);
+
+        (0..400)
+            .map(|i| {
+                let topic = &topics[i % 2];
+
+                (0..8).map(|_| topic[rng.below(topic.len())].to_string()).collect()
+            })
+            .collect()
+    }
+
+    pub(crate) fn check_topics(model: &dyn WordVectorModel) {
+        let vector = |word: &str| model.word_to_vector(word).unwrap();
+
+        let same = Metric::Cosine.distance(&vector("снег"), &vector("метель"));
+        let other = Metric::Cosine.distance(&vector("снег"), &vector("пляж"));
+
+        assert!(same < other, "check topic words are closer {} < {}", same, other);
+    }
+
+    fn word2vec() -> Word2Vec {
+        Word2Vec {
+            dim: 16,
+            window: 3,
+            min_count: 1,
+            sample: 0.0,
+            epochs: 10,
+            threads: 1,
+            ..Word2Vec::default()
+        }
+    }
+
+    #[test]
+    fn test_word2vec_skip_gram() {
+        let corpus = topic_corpus();
+
+        let model = word2vec().train(&corpus).unwrap();
+
+        assert_eq!(model.len(), 10, "check vocabulary");
+        assert_eq!(model.dim(), 16, "check dimension");
+
+        check_topics(&model);
+    }
+
+    #[test]
+    fn test_word2vec_cbow_hierarchical_softmax() {
+        let corpus = topic_corpus();
+
+        let config = Word2Vec {
+            architecture: Architecture::Cbow,
+            loss: Loss::HierarchicalSoftmax,
+            alpha: 0.05,
+            ..word2vec()
+        };
+
+        let model = config.train(&corpus).unwrap();
+
+        assert_eq!(model.len(), 10, "check vocabulary");
+        check_topics(&model);
+
+        let again = config.train(&corpus).unwrap();
+        assert_eq!(model.as_slice(), again.as_slice(), "check reproducibility");
+    }
+
+    #[test]
+    fn test_word2vec_mixed_modes() {
+        let corpus = topic_corpus();
+
+        let cbow = Word2Vec { architecture: Architecture::Cbow, alpha: 0.05, ..word2vec() };
+        check_topics(&cbow.train(&corpus).unwrap());
+
+        let skip_gram = Word2Vec { loss: Loss::HierarchicalSoftmax, ..word2vec() };
+        check_topics(&skip_gram.train(&corpus).unwrap());
+    }
+
+    #[test]
+    fn test_word2vec_deterministic() {
+        let corpus = topic_corpus();
+
+        let model1 = word2vec().train(&corpus).unwrap();
+        let model2 = word2vec().train(&corpus).unwrap();
+
+        assert_eq!(model1.as_slice(), model2.as_slice(), "check single thread reproducibility");
+
+        let parallel = Word2Vec { threads: 4, ..word2vec() }.train(&corpus).unwrap();
+        assert_eq!(parallel.len(), 10, "check parallel training");
+    }
+
+    #[test]
+    fn test_word2vec_errors() {
+        let empty: Vec<Vec<&str>> = vec![vec!["снег"]];
+
+        assert!(Word2Vec { min_count: 2, ..word2vec() }.train(&empty).is_err(), "check empty vocabulary");
+        assert!(Word2Vec { dim: 0, ..word2vec() }.train(&empty).is_err(), "check parameters");
+    }
+}
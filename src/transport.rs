@@ -0,0 +1,275 @@
+use coverage::Coverage;
+use wordvector::SINGLE_WORD_DISTANCE;
+use WordVector;
+
+// Mass of a document 1 word moved to a document 2 word,
+// cost is the ground distance between the words
+#[derive(Debug, Clone, PartialEq)]
+pub struct Flow {
+    pub word1: String,
+    pub word2: String,
+    pub mass: f32,
+    pub cost: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmdExplanation {
+    pub distance: f32,
+    // sorted by the contribution mass * cost descending
+    pub flows: Vec<Flow>,
+}
+
+const MASS_EPSILON: f32 = 1e-7;
+
+// Exact balanced transport by successive shortest paths, cost is rows x cols row-major,
+// supply and demand must have equal sums. Returns the flow matrix in the cost layout
+pub(crate) fn transport_plan(supply: &[f32], demand: &[f32], cost: &[f32]) -> Vec<f32> {
+    let (rows, cols) = (supply.len(), demand.len());
+
+    let mut supply = supply.to_vec();
+    let mut demand = demand.to_vec();
+    let mut flow: Vec<f32> = vec![0.0; rows * cols];
+
+    // nodes are rows 0..rows and columns rows..rows + cols
+    let mut dist: Vec<f32> = vec![0.0; rows + cols];
+    let mut pred: Vec<Option<usize>> = vec![None; rows + cols];
+
+    loop {
+        for node in 0..rows + cols {
+            dist[node] = if node < rows && supply[node] > MASS_EPSILON { 0.0 } else { f32::INFINITY };
+            pred[node] = None;
+        }
+
+        // Bellman-Ford over the residual graph: forward edges row -> column,
+        // backward edges column -> row where a flow exists
+        for _ in 0..rows + cols {
+            let mut changed = false;
+
+            for i in 0..rows {
+                if !dist[i].is_finite() {
+                    continue;
+                }
+
+                for j in 0..cols {
+                    let value = dist[i] + cost[i * cols + j];
+
+                    if value < dist[rows + j] - MASS_EPSILON {
+                        dist[rows + j] = value;
+                        pred[rows + j] = Some(i);
+                        changed = true;
+                    }
+                }
+            }
+
+            for j in 0..cols {
+                if !dist[rows + j].is_finite() {
+                    continue;
+                }
+
+                for i in (0..rows).filter(|&i| flow[i * cols + j] > MASS_EPSILON) {
+                    let value = dist[rows + j] - cost[i * cols + j];
+
+                    if value < dist[i] - MASS_EPSILON {
+                        dist[i] = value;
+                        pred[i] = Some(rows + j);
+                        changed = true;
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        let sink = (0..cols)
+            .filter(|&j| demand[j] > MASS_EPSILON && dist[rows + j].is_finite())
+            .min_by(|&j1, &j2| dist[rows + j1].total_cmp(&dist[rows + j2]));
+
+        let sink = match sink {
+            Some(sink) => sink,
+            None => break,
+        };
+
+        // walk back to the source row and find the bottleneck
+        let mut path: Vec<(usize, usize, bool)> = Vec::new();
+        let mut node = rows + sink;
+
+        while let Some(prev) = pred[node] {
+            if node >= rows {
+                path.push((prev, node - rows, true));
+            } else {
+                path.push((node, prev - rows, false));
+            }
+
+            node = prev;
+        }
+
+        let source = node;
+
+        let mut mass = supply[source].min(demand[sink]);
+
+        for &(i, j, _) in path.iter().filter(|&&(_, _, forward)| !forward) {
+            mass = mass.min(flow[i * cols + j]);
+        }
+
+        for &(i, j, forward) in path.iter() {
+            if forward {
+                flow[i * cols + j] += mass;
+            } else {
+                flow[i * cols + j] -= mass;
+            }
+        }
+
+        supply[source] -= mass;
+        demand[sink] -= mass;
+    }
+
+    flow
+}
+
+fn normalized(bow: &[f32]) -> Vec<f32> {
+    let sum: f32 = bow.iter().sum();
+
+    bow.iter().map(|&v| v / sum).collect()
+}
+
+impl<'a> WordVector<'a> {
+    // Word mover's distance with the optimal transport plan, solved by the built-in exact solver
+    // on the histograms of known words, so the distance may differ from `wm_distance`
+    // when a document has out of vocabulary words
+    pub fn wm_distance_explained<T>(&self, doc1: &[T], doc2: &[T]) -> Result<WmdExplanation, &str>
+        where
+            T: ToString
+    {
+        self.wm_distance_explained_with_coverage(doc1, doc2).map(|(explanation, _, _)| explanation)
+    }
+
+    pub fn wm_distance_explained_with_coverage<T>(&self, doc1: &[T], doc2: &[T]) -> Result<(WmdExplanation, Coverage, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage1 = self.check_coverage(doc1)?;
+        let coverage2 = self.check_coverage(doc2)?;
+
+        Ok((self.explain(doc1, doc2)?, coverage1, coverage2))
+    }
+
+    fn explain<T>(&self, doc1: &[T], doc2: &[T]) -> Result<WmdExplanation, &str>
+        where
+            T: ToString
+    {
+        let problem = match self.wmd_problem(doc1, doc2)? {
+            Some(problem) => problem,
+            None => {
+                // both documents consist of the same single word, same distance as wm_distance
+                let word = match self.dictionary(doc1).iter().next() {
+                    Some(word) => word.clone(),
+                    None => return Err("empty dictionary"),
+                };
+
+                return Ok(WmdExplanation {
+                    distance: SINGLE_WORD_DISTANCE,
+                    flows: vec![Flow { word1: word.clone(), word2: word, mass: 1.0, cost: SINGLE_WORD_DISTANCE }],
+                });
+            }
+        };
+
+        let (words1, words2, cost) = (&problem.words1, &problem.words2, problem.cost.as_slice());
+        let cols = words2.len();
+
+        let flow = transport_plan(&normalized(&problem.doc_bow1), &normalized(&problem.doc_bow2), cost);
+
+        let mut flows: Vec<Flow> = flow.iter()
+            .enumerate()
+            .filter(|&(_, &mass)| mass > MASS_EPSILON)
+            .map(|(pos, &mass)| Flow {
+                word1: words1[pos / cols].clone(),
+                word2: words2[pos % cols].clone(),
+                mass,
+                cost: cost[pos],
+            })
+            .collect();
+
+        flows.sort_by(|flow1, flow2| (flow2.mass * flow2.cost).total_cmp(&(flow1.mass * flow1.cost)));
+
+        Ok(WmdExplanation {
+            distance: flows.iter().map(|flow| flow.mass * flow.cost).sum(),
+            flows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+    use testing::model::TestModel;
+    use metric::Metric;
+    use {CostLayout, WordMetric, WordVectorModel};
+
+    fn plan_cost(flow: &[f32], cost: &[f32]) -> f32 {
+        flow.iter().zip(cost.iter()).map(|(f, c)| f * c).sum()
+    }
+
+    #[test]
+    fn test_transport_plan() {
+        let cost = [0.0f32, 1.0, 1.0, 0.0];
+        assert_eq!(transport_plan(&[0.5, 0.5], &[0.5, 0.5], &cost), [0.5, 0.0, 0.0, 0.5], "check diagonal");
+
+        let flow = transport_plan(&[1.0], &[0.25, 0.75], &[1.0, 2.0]);
+        assert_eq!(flow, [0.25, 0.75], "check split");
+
+        // the cheapest first step has to be undone through a backward edge
+        let cost = [1.0f32, 2.0, 1.0, 10.0];
+        let flow = transport_plan(&[0.5, 0.5], &[0.5, 0.5], &cost);
+        assert_eq!(flow, [0.0, 0.5, 0.5, 0.0], "check rerouting");
+        assert_eq!(plan_cost(&flow, &cost), 1.5, "check optimal cost");
+    }
+
+    #[test]
+    fn test_wm_distance_explained() {
+        let model = TestModel::default();
+
+        let doc1 = ["намело", "сугробы", "калитки"];
+        let doc2 = ["сугробы", "у", "крыльца", "крыльца"];
+
+        let explained: Vec<WmdExplanation> = [CostLayout::Partial, CostLayout::Symmetric, CostLayout::Compact]
+            .iter()
+            .map(|&layout| {
+                WordVector::new(&model, &model)
+                    .with_cost_layout(layout)
+                    .wm_distance_explained(&doc1, &doc2)
+                    .unwrap()
+            })
+            .collect();
+
+        let exist = &explained[0];
+
+        let mass1: f32 = exist.flows.iter().filter(|flow| flow.word1 == "намело").map(|flow| flow.mass).sum();
+        let mass2: f32 = exist.flows.iter().filter(|flow| flow.word2 == "крыльца").map(|flow| flow.mass).sum();
+
+        assert!((mass1 - 0.5).abs() < 1e-6, "check unknown words are not transported {}", mass1);
+        assert!((mass2 - 0.5).abs() < 1e-6, "check repeated word mass {}", mass2);
+        assert!(exist.flows.iter().all(|flow| flow.word1 != "калитки"), "check unknown word");
+
+        for flow in exist.flows.iter() {
+            let cost = Metric::Euclidean.distance(&model.word_to_vector(&flow.word1).unwrap(),
+                                                  &model.word_to_vector(&flow.word2).unwrap());
+
+            assert!((flow.cost - cost).abs() < 1e-6, "check flow cost {} -> {}", flow.word1, flow.word2);
+        }
+
+        let total: f32 = exist.flows.iter().map(|flow| flow.mass * flow.cost).sum();
+        assert!((exist.distance - total).abs() < 1e-6, "check distance");
+
+        for other in explained.iter().skip(1) {
+            assert!((other.distance - exist.distance).abs() < 1e-5, "check layouts {} {}", other.distance, exist.distance);
+        }
+
+        let vector = WordVector::new(&model, &model);
+
+        let same = vector.wm_distance_explained(&["у", "у"], &["у"]).unwrap();
+        assert_eq!(same.distance, vector.wm_distance(&["у", "у"], &["у"]).unwrap(), "check same single word");
+        assert_eq!(same.flows.len(), 1, "check single flow");
+    }
+}
@@ -1,25 +1,96 @@
+use std::io::{self, Read};
+
+use kernels;
+
 #[cfg(test)]
-pub(crate) fn doc_parse<'a>(doc: &'a str) -> Vec<&'a str> {
+pub(crate) fn doc_parse(doc: &str) -> Vec<&str> {
     doc.split_whitespace().collect()
 }
 
-// TODO: make macros
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// exactly len bytes; the buffer grows with the data read, so a corrupted length
+// fails at the end of the input instead of allocating it upfront
+pub(crate) fn read_bytes<R>(reader: &mut R, len: usize) -> io::Result<Vec<u8>>
+    where R: Read
+{
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of data"));
+    }
+
+    Ok(bytes)
+}
+
+// mean of the vectors of the whitespace separated words, None if no word has one
+pub(crate) fn sentence_mean<F, V>(text: &str, dim: usize, vector: F) -> Option<Vec<f32>>
+    where
+        F: Fn(&str) -> Option<V>,
+        V: AsRef<[f32]>
+{
+    words_mean(text.split_whitespace(), dim, vector)
+}
+
+pub(crate) fn words_mean<I, F, V>(words: I, dim: usize, vector: F) -> Option<Vec<f32>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+        F: Fn(&str) -> Option<V>,
+        V: AsRef<[f32]>
+{
+    let mut doc_vec: Vec<f32> = vec![0.0; dim];
+    let mut count = 0;
+
+    for vector in words.into_iter().filter_map(|word| vector(word.as_ref())) {
+        kernels::axpy(1.0, vector.as_ref(), &mut doc_vec);
+
+        count += 1;
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    doc_vec.iter_mut()
+        .for_each(|v| *v /= count as f32);
+
+    Some(doc_vec)
+}
+
+// sum into a new vector, longer vectors are truncated
+#[deprecated(note = "use vecops::sum, it checks the dimensions")]
 pub fn vec_sum<T>(vecs: T) -> Vec<f32>
     where T: IntoIterator<Item=Vec<f32>>
 {
     vecs.into_iter().fold(Vec::new(), |mut acc, vector| {
         if acc.is_empty() {
-            acc.resize_default(vector.len());
+            acc.resize(vector.len(), 0.0);
         }
 
-        acc.iter_mut()
-            .zip(vector.iter())
-            .for_each(|(v1, v2)| *v1 += v2);
+        kernels::axpy(1.0, &vector, &mut acc);
 
         acc
     })
 }
 
+// the k smallest (index, distance) pairs ordered by f32::total_cmp, ties by index
+pub(crate) fn smallest_k(mut distances: Vec<(usize, f32)>, k: usize) -> Vec<(usize, f32)> {
+    let order = |&(i1, d1): &(usize, f32), &(i2, d2): &(usize, f32)| d1.total_cmp(&d2).then(i1.cmp(&i2));
+
+    if k < distances.len() {
+        distances.select_nth_unstable_by(k, order);
+        distances.truncate(k);
+    }
+
+    distances.sort_unstable_by(order);
+
+    distances
+}
+
 #[cfg(test)]
 mod testing {
     use super::*;
@@ -33,6 +104,7 @@ mod testing {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn test_vec_sum() {
         let data = vec![
             vec![1.0f32, 2.0, 3.0, 4.0, 5.0],
@@ -46,4 +118,13 @@ mod testing {
 
         assert_eq!(exist, expected, "check vectors sum");
     }
+
+    #[test]
+    fn test_smallest_k() {
+        let distances = vec![(0, 3.0f32), (1, f32::NAN), (2, 1.0), (3, 2.0), (4, 1.0)];
+
+        assert_eq!(smallest_k(distances.clone(), 3), [(2, 1.0), (4, 1.0), (3, 2.0)], "check smallest");
+        assert_eq!(smallest_k(distances.clone(), 0), [], "check empty");
+        assert_eq!(smallest_k(distances, 10).last().unwrap().0, 1, "check NaN last");
+    }
 }
\ No newline at end of file
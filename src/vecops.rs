@@ -0,0 +1,191 @@
+// Arithmetic on f32 slices. Results go into a caller buffer or replace the
+// first argument, slices of different lengths are an error instead of a `zip` truncation
+
+use kernels;
+
+const DIMENSION_MISMATCH: &str = "vector dimension mismatch";
+
+fn check(len1: usize, len2: usize) -> Result<(), &'static str> {
+    if len1 != len2 {
+        return Err(DIMENSION_MISMATCH);
+    }
+
+    Ok(())
+}
+
+// acc += x
+pub fn add(acc: &mut [f32], x: &[f32]) -> Result<(), &'static str> {
+    add_scaled(acc, 1.0, x)
+}
+
+// acc -= x
+pub fn sub(acc: &mut [f32], x: &[f32]) -> Result<(), &'static str> {
+    add_scaled(acc, -1.0, x)
+}
+
+// acc += a * x
+pub fn add_scaled(acc: &mut [f32], a: f32, x: &[f32]) -> Result<(), &'static str> {
+    check(acc.len(), x.len())?;
+
+    kernels::axpy(a, x, acc);
+
+    Ok(())
+}
+
+// element-wise acc *= x
+pub fn mul(acc: &mut [f32], x: &[f32]) -> Result<(), &'static str> {
+    check(acc.len(), x.len())?;
+
+    acc.iter_mut().zip(x.iter()).for_each(|(v1, v2)| *v1 *= v2);
+
+    Ok(())
+}
+
+// element-wise acc /= x
+pub fn div(acc: &mut [f32], x: &[f32]) -> Result<(), &'static str> {
+    check(acc.len(), x.len())?;
+
+    acc.iter_mut().zip(x.iter()).for_each(|(v1, v2)| *v1 /= v2);
+
+    Ok(())
+}
+
+pub fn scale(vec: &mut [f32], a: f32) {
+    vec.iter_mut().for_each(|v| *v *= a);
+}
+
+// to unit L2 norm, a zero vector is kept; returns the norm before
+pub fn normalize(vec: &mut [f32]) -> f32 {
+    let norm = kernels::norm(vec);
+
+    if norm > 0.0 {
+        vec.iter_mut().for_each(|v| *v /= norm);
+    }
+
+    norm
+}
+
+// out = sum of the vectors, a zero vector for none
+pub fn sum<I>(out: &mut [f32], vectors: I) -> Result<(), &'static str>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[f32]>
+{
+    weighted_sum(out, vectors.into_iter().map(|vector| (vector, 1.0)))
+}
+
+// out = sum of weight * vector
+pub fn weighted_sum<I, V>(out: &mut [f32], vectors: I) -> Result<(), &'static str>
+    where
+        I: IntoIterator<Item=(V, f32)>,
+        V: AsRef<[f32]>
+{
+    out.iter_mut().for_each(|v| *v = 0.0);
+
+    for (vector, weight) in vectors {
+        add_scaled(out, weight, vector.as_ref())?;
+    }
+
+    Ok(())
+}
+
+// out = mean of the vectors, fails for none; out is overwritten on errors too
+pub fn mean<I>(out: &mut [f32], vectors: I) -> Result<(), &'static str>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[f32]>
+{
+    out.iter_mut().for_each(|v| *v = 0.0);
+
+    let mut count = 0;
+
+    for vector in vectors {
+        add(out, vector.as_ref())?;
+        count += 1;
+    }
+
+    if count == 0 {
+        return Err("no vectors");
+    }
+
+    scale(out, 1.0 / count as f32);
+
+    Ok(())
+}
+
+pub fn dot(vec1: &[f32], vec2: &[f32]) -> Result<f32, &'static str> {
+    check(vec1.len(), vec2.len())?;
+
+    Ok(kernels::dot(vec1, vec2))
+}
+
+// zero for a zero vector
+pub fn cosine(vec1: &[f32], vec2: &[f32]) -> Result<f32, &'static str> {
+    let dot = dot(vec1, vec2)?;
+    let norm = kernels::norm(vec1) * kernels::norm(vec2);
+
+    if norm == 0.0 {
+        return Ok(0.0);
+    }
+
+    Ok((dot / norm).clamp(-1.0, 1.0))
+}
+
+pub fn squared_euclidean(vec1: &[f32], vec2: &[f32]) -> Result<f32, &'static str> {
+    check(vec1.len(), vec2.len())?;
+
+    Ok(kernels::squared_l2(vec1, vec2))
+}
+
+pub fn euclidean(vec1: &[f32], vec2: &[f32]) -> Result<f32, &'static str> {
+    Ok(squared_euclidean(vec1, vec2)?.sqrt())
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_vecops() {
+        let vectors = vec![vec![1.0f32, 2.0, 3.0], vec![3.0f32, 2.0, 1.0]];
+        let mut out = [7.0f32; 3];
+
+        sum(&mut out, &vectors).unwrap();
+        assert_eq!(out, [4.0, 4.0, 4.0], "check sum");
+
+        weighted_sum(&mut out, vectors.iter().zip([1.0f32, 0.5].iter().cloned())).unwrap();
+        assert_eq!(out, [2.5, 3.0, 3.5], "check weighted sum");
+
+        mean(&mut out, &vectors).unwrap();
+        assert_eq!(out, [2.0, 2.0, 2.0], "check mean");
+
+        sub(&mut out, &[1.0, 1.0, 1.0]).unwrap();
+        mul(&mut out, &[3.0, 4.0, 0.0]).unwrap();
+        assert_eq!(out, [3.0, 4.0, 0.0], "check element-wise");
+
+        div(&mut out, &[3.0, 2.0, 1.0]).unwrap();
+        assert_eq!(out, [1.0, 2.0, 0.0], "check division");
+
+        let mut unit = [3.0f32, 4.0];
+        assert_eq!(normalize(&mut unit), 5.0, "check norm");
+        assert_eq!(unit, [0.6, 0.8], "check normalize");
+
+        assert_eq!(dot(&[1.0, 2.0], &[3.0, 4.0]), Ok(11.0), "check dot");
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 2.0]), Ok(0.0), "check cosine");
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), Ok(0.0), "check zero cosine");
+        assert_eq!(euclidean(&[1.0, 0.0], &[4.0, 4.0]), Ok(5.0), "check euclidean");
+
+        assert!(mean(&mut out, Vec::<Vec<f32>>::new()).is_err(), "check empty mean");
+    }
+
+    #[test]
+    fn test_vecops_mismatch() {
+        let mut out = [0.0f32; 2];
+
+        assert_eq!(add(&mut out, &[1.0, 2.0, 3.0]), Err(DIMENSION_MISMATCH), "check add");
+        assert_eq!(sum(&mut out, &[vec![1.0f32, 2.0], vec![1.0]]), Err(DIMENSION_MISMATCH), "check sum");
+        assert_eq!(dot(&[1.0], &[1.0, 2.0]), Err(DIMENSION_MISMATCH), "check dot");
+        assert_eq!(euclidean(&[1.0], &[]), Err(DIMENSION_MISMATCH), "check euclidean");
+        assert!(cosine(&[1.0, 2.0], &[1.0]).is_err(), "check cosine");
+    }
+}
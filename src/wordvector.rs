@@ -1,13 +1,69 @@
+use coverage::Coverage;
 use dictionary::Dictionary;
+use encoder::Mean;
+use kernels;
 use matrix::Matrix;
-use utils::vec_sum;
-use {WordVector, WordVectorModel, WordVectorDistance};
+use metric::Metric;
+use pairwise;
+use {WordVector, WordVectorModel, WordVectorDistance, SentenceEncoder, WordMetric, CostLayout};
+
+// wm distance of two docs of the same single word, there is no transport problem to solve
+pub(crate) const SINGLE_WORD_DISTANCE: f32 = 1.0;
+
+// A transport problem between two documents: word histograms
+// and the ground cost matrix in the layout the solver receives,
+// words1 and words2 name the rows and the columns of the cost matrix
+pub(crate) struct WmdProblem {
+    pub layout: CostLayout,
+    pub words1: Vec<String>,
+    pub words2: Vec<String>,
+    pub doc_bow1: Vec<f32>,
+    pub doc_bow2: Vec<f32>,
+    pub cost: Matrix<f32>,
+}
+
+fn dictionary_words(dict: &Dictionary) -> Vec<String> {
+    dict.iter_indexed().map(|(_, word)| word.to_string()).collect()
+}
 
 impl<'a> WordVector<'a> {
-    pub fn new(model: &'a WordVectorModel, distance: &'a WordVectorDistance) -> WordVector<'a> {
+    pub fn new(model: &'a dyn WordVectorModel, distance: &'a dyn WordVectorDistance) -> WordVector<'a> {
         WordVector {
             model,
             distance,
+            encoder: &Mean,
+            metric: &Metric::Euclidean,
+            layout: CostLayout::Partial,
+            min_coverage: 0.0,
+        }
+    }
+
+    pub fn with_encoder(self, encoder: &'a dyn SentenceEncoder) -> WordVector<'a> {
+        WordVector {
+            encoder,
+            ..self
+        }
+    }
+
+    pub fn with_metric(self, metric: &'a dyn WordMetric) -> WordVector<'a> {
+        WordVector {
+            metric,
+            ..self
+        }
+    }
+
+    pub fn with_cost_layout(self, layout: CostLayout) -> WordVector<'a> {
+        WordVector {
+            layout,
+            ..self
+        }
+    }
+
+    // document-level methods fail when the share of known tokens of a document is lower
+    pub fn with_min_coverage(self, min_coverage: f32) -> WordVector<'a> {
+        WordVector {
+            min_coverage,
+            ..self
         }
     }
 
@@ -20,7 +76,7 @@ impl<'a> WordVector<'a> {
         for w in doc {
             let word: String = w.to_string();
 
-            if let Some(_) = self.model.word_index(&word) {
+            if self.model.word_index(&word).is_some() {
                 dict.insert(word);
             }
         }
@@ -28,31 +84,47 @@ impl<'a> WordVector<'a> {
         dict
     }
 
-    pub(crate) fn doc_to_unite_core<T>(&self, doc: &[T]) -> Result<Vec<f32>, &str>
+    // a doc the encoder finds no vectors for is an "empty doc vector" error,
+    // so similarity with it fails instead of being 0
+    pub fn doc_to_vector<T>(&self, doc: &[T]) -> Result<Vec<f32>, &str>
+        where
+            T: ToString
+    {
+        self.doc_to_vector_with_coverage(doc).map(|(vector, _)| vector)
+    }
+
+    pub fn doc_to_vector_with_coverage<T>(&self, doc: &[T]) -> Result<(Vec<f32>, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage = self.check_coverage(doc)?;
+
+        Ok((self.encode(doc)?, coverage))
+    }
+
+    // the encoder output without the coverage check
+    fn encode<T>(&self, doc: &[T]) -> Result<Vec<f32>, &str>
         where
             T: ToString
     {
-        let doc_len = doc.len() as f32;
+        let doc: Vec<String> = doc.iter().map(|word| word.to_string()).collect();
 
-        let mut unite_core: Vec<f32> = vec_sum(
-            doc.iter()
-            .filter_map(|word|
-                self.model.word_to_vector(&word.to_string())
-            )
-        );
+        match self.encoder.encode(self.model, &doc) {
+            Some(vector) => Ok(vector),
+            None => Err("empty doc vector"),
+        }
+    }
 
-        // TODO: NORMALIZE
-        unite_core.iter_mut()
-            .for_each(|v| *v /= doc_len);
+    // the doc vector of unit norm, the coverage is checked by the caller
+    pub(crate) fn doc_to_unite_core<T>(&self, doc: &[T]) -> Result<Vec<f32>, &str>
+        where
+            T: ToString
+    {
+        let mut unite_core: Vec<f32> = self.encode(doc)?;
 
-        let distance: f32 = unite_core.iter()
-            .fold(0.0f32, |acc, v| {
-                acc + v * v
-            })
-            .sqrt();
+        let distance = kernels::norm(&unite_core);
 
         if distance > 0.0f32 {
-            // TODO: NORMALIZE
             unite_core.iter_mut()
                 .for_each(|v| *v /=  distance)
         }
@@ -65,23 +137,90 @@ impl<'a> WordVector<'a> {
     }
 
     pub fn words_distance(&self, word1: &str, word2: &str) -> Option<f32> {
-        let vec1 = self.model.word_to_vector(&word1)?;
-        let vec2 = self.model.word_to_vector(&word2)?;
-        let distance: f32 = vec1.iter()
-            .zip(vec2.iter())
-            .map(|(&v1, v2)| v1 - v2)
-            .map(|v| v*v)
-            .sum();
+        let vec1 = self.model.word_to_vector(word1)?;
+        let vec2 = self.model.word_to_vector(word2)?;
 
-        Some(distance.sqrt())
+        Some(self.metric.distance(&vec1, &vec2))
     }
 
-    pub fn wm_distance<T>(&self, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+    pub fn words_similarity(&self, word1: &str, word2: &str) -> Option<f32> {
+        let vec1 = self.model.word_to_vector(word1)?;
+        let vec2 = self.model.word_to_vector(word2)?;
+
+        let dot = kernels::dot(&vec1, &vec2);
+        let norm1 = kernels::norm(&vec1);
+        let norm2 = kernels::norm(&vec2);
+
+        if norm1 == 0.0 || norm2 == 0.0 {
+            return None;
+        }
+
+        Some(dot / (norm1 * norm2))
+    }
+
+    pub(crate) fn words_matrix<F>(&self, dict: &Dictionary, value: F) -> Matrix<f32>
+        where
+            F: Fn(&str, &str) -> Option<f32>
+    {
+        let mut matrix = Matrix::square(dict.len());
+
+        for (i, word1) in dict.iter().enumerate() {
+            for (j, word2) in dict.iter().enumerate() {
+                if let Some(v) = value(word1, word2) {
+                    matrix[(i, j)] = v;
+                }
+            }
+        }
+
+        matrix
+    }
+
+    // vectors of the known words stacked row-major, with the positions of these words
+    fn stacked_vectors(&self, words: &[&str]) -> (Vec<usize>, Vec<f32>) {
+        let mut positions: Vec<usize> = Vec::new();
+        let mut vectors: Vec<f32> = Vec::new();
+
+        for (i, word) in words.iter().enumerate() {
+            if let Some(vector) = self.model.word_to_vector(word) {
+                positions.push(i);
+                vectors.extend_from_slice(&vector);
+            }
+        }
+
+        (positions, vectors)
+    }
+
+    // words1 x words2 distances by the metric in one tiled pass, zero for unknown words.
+    // Each cost is exactly the metric distance of the pair, not the expanded batch form,
+    // so wm_distance does not depend on the size of the documents
+    pub(crate) fn words_distances(&self, words1: &[&str], words2: &[&str]) -> Matrix<f32> {
+        let mut distances = Matrix::new(words1.len(), words2.len());
+
+        let (positions1, vectors1) = self.stacked_vectors(words1);
+        let (positions2, vectors2) = self.stacked_vectors(words2);
+
+        if positions1.is_empty() || positions2.is_empty() {
+            return distances;
+        }
+
+        let known = pairwise::map(&vectors1, &vectors2, vectors1.len() / positions1.len(), |vec1, vec2| self.metric.distance(vec1, vec2));
+
+        for (k1, &i) in positions1.iter().enumerate() {
+            for (k2, &j) in positions2.iter().enumerate() {
+                distances[(i, j)] = known[(k1, k2)];
+            }
+        }
+
+        distances
+    }
+
+    // the coverage is checked by the caller
+    pub(crate) fn wmd_problem<T>(&self, doc1: &[T], doc2: &[T]) -> Result<Option<WmdProblem>, &str>
         where
             T: ToString
     {
-        let dict1 = self.dictionary(&doc1);
-        let dict2 = self.dictionary(&doc2);
+        let mut dict1 = self.dictionary(doc1);
+        let mut dict2 = self.dictionary(doc2);
 
         if dict1.is_empty() || dict2.is_empty() {
             return Err("empty dictionary");
@@ -89,49 +228,165 @@ impl<'a> WordVector<'a> {
 
         let dict = dict1.join(&dict2);
         if dict.len() <= 1 {
-            return Ok(1.0);
+            return Ok(None);
+        }
+
+        if self.layout == CostLayout::Compact {
+            dict1.reindex();
+            dict2.reindex();
+
+            let doc_bow1 = match dict1.bow_normalized(doc1) {
+                Some(bow) => bow,
+                None => return Err("empty doc bow"),
+            };
+
+            let doc_bow2 = match dict2.bow_normalized(doc2) {
+                Some(bow) => bow,
+                None => return Err("empty doc bow"),
+            };
+
+            let words1: Vec<&str> = dict1.iter().map(|word| word.as_str()).collect();
+            let words2: Vec<&str> = dict2.iter().map(|word| word.as_str()).collect();
+
+            let cost = self.words_distances(&words1, &words2);
+
+            return Ok(Some(WmdProblem {
+                layout: CostLayout::Compact,
+                words1: dictionary_words(&dict1),
+                words2: dictionary_words(&dict2),
+                doc_bow1,
+                doc_bow2,
+                cost,
+            }));
         }
 
-        let doc_bow1 = match dict.bow_normalized(&doc1) {
+        let doc_bow1 = match dict.bow_normalized(doc1) {
             Some(bow) => bow,
             None => return Err("empty doc bow"),
         };
 
-        let doc_bow2 = match dict.bow_normalized(&doc2) {
+        let doc_bow2 = match dict.bow_normalized(doc2) {
             Some(bow) => bow,
             None => return Err("empty doc bow"),
         };
 
-        let mut matrix = Matrix::new(dict.len());
+        let len = dict.len();
 
-        {
-            let mut matrix_2d = matrix.as_matrix_mut();
+        let mut cost = Matrix::square(len);
 
-            for (i, word1) in dict.iter().enumerate() {
-                for (j, word2) in dict.iter().enumerate() {
-                    if dict1.contains(&word1) && dict2.contains(&word2) {
-                        if let Some(distance) = self.words_distance(&word1, &word2) {
-                            matrix_2d[i][j] = distance;
-                        }
-                    }
+        if self.layout == CostLayout::Symmetric {
+            let words: Vec<&str> = dict.iter().map(|word| word.as_str()).collect();
+            let distances = self.words_distances(&words, &words);
+
+            // the upper triangle is mirrored so that the matrix is exactly symmetric
+            for i in 0..len {
+                for j in i + 1..len {
+                    cost[(i, j)] = distances[(i, j)];
+                    cost[(j, i)] = distances[(i, j)];
+                }
+            }
+        } else {
+            // only the rows of doc1 words and the columns of doc2 words are filled
+            let words1: Vec<&str> = dict1.iter().map(|word| word.as_str()).collect();
+            let words2: Vec<&str> = dict2.iter().map(|word| word.as_str()).collect();
+
+            let index = |word: &&str| dict.word_index(word).expect("joined dictionary has every word") as usize;
+            let rows: Vec<usize> = words1.iter().map(index).collect();
+            let cols: Vec<usize> = words2.iter().map(index).collect();
+
+            let distances = self.words_distances(&words1, &words2);
+
+            for (k1, &i) in rows.iter().enumerate() {
+                for (k2, &j) in cols.iter().enumerate() {
+                    cost[(i, j)] = distances[(k1, k2)];
                 }
             }
         }
 
-        Ok(self.distance.calc(&doc_bow1, &doc_bow2, matrix.as_slice()))
+        Ok(Some(WmdProblem {
+            layout: self.layout,
+            words1: dictionary_words(&dict),
+            words2: dictionary_words(&dict),
+            doc_bow1,
+            doc_bow2,
+            cost,
+        }))
+    }
+
+    pub fn wm_distance<T>(&self, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
+        where
+            T: ToString
+    {
+        self.wm_distance_with_coverage(doc1, doc2).map(|(distance, _, _)| distance)
+    }
+
+    pub fn wm_distance_with_coverage<T>(&self, doc1: &[T], doc2: &[T]) -> Result<(f32, Coverage, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage1 = self.check_coverage(doc1)?;
+        let coverage2 = self.check_coverage(doc2)?;
+
+        let problem = match self.wmd_problem(doc1, doc2)? {
+            Some(problem) => problem,
+            None => return Ok((SINGLE_WORD_DISTANCE, coverage1, coverage2)),
+        };
+
+        let distance = self.distance.calc(problem.layout, &problem.doc_bow1, &problem.doc_bow2, problem.cost.as_slice());
+
+        Ok((distance, coverage1, coverage2))
     }
 
     pub fn similarity<T>(&self, doc1: &[T], doc2: &[T]) -> Result<f32, &str>
         where
             T: ToString
     {
-        let unit_core1 = self.doc_to_unite_core(&doc1)?;
-        let unit_core2 = self.doc_to_unite_core(&doc2)?;
-
-        // TODO: MUL
-        Ok(unit_core1.iter()
-            .zip(unit_core2.iter())
-            .map(|(v1, v2)| v1 * v2)
-            .sum())
+        self.similarity_with_coverage(doc1, doc2).map(|(similarity, _, _)| similarity)
+    }
+
+    pub fn similarity_with_coverage<T>(&self, doc1: &[T], doc2: &[T]) -> Result<(f32, Coverage, Coverage), &str>
+        where
+            T: ToString
+    {
+        let coverage1 = self.check_coverage(doc1)?;
+        let coverage2 = self.check_coverage(doc2)?;
+
+        let unit_core1 = self.doc_to_unite_core(doc1)?;
+        let unit_core2 = self.doc_to_unite_core(doc2)?;
+
+        Ok((kernels::dot(&unit_core1, &unit_core2), coverage1, coverage2))
+    }
+
+    // docs x docs distances between the document vectors by the metric
+    pub fn distance_matrix<T>(&self, docs: &[Vec<T>]) -> Result<Matrix<f32>, &str>
+        where
+            T: ToString
+    {
+        let mut vectors: Vec<f32> = Vec::new();
+
+        for doc in docs {
+            vectors.extend(self.doc_to_vector(doc)?);
+        }
+
+        let dim = if docs.is_empty() { 0 } else { vectors.len() / docs.len() };
+
+        Ok(self.metric.distances(&vectors, &vectors, dim))
+    }
+
+    // docs x docs values of `similarity`
+    pub fn similarity_matrix<T>(&self, docs: &[Vec<T>]) -> Result<Matrix<f32>, &str>
+        where
+            T: ToString
+    {
+        let mut unit_cores: Vec<f32> = Vec::new();
+
+        for doc in docs {
+            self.check_coverage(doc)?;
+            unit_cores.extend(self.doc_to_unite_core(doc)?);
+        }
+
+        let dim = if docs.is_empty() { 0 } else { unit_cores.len() / docs.len() };
+
+        Ok(pairwise::dot_products(&unit_cores, &unit_cores, dim))
     }
 }